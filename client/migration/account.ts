@@ -0,0 +1,50 @@
+import * as anchor from "@coral-xyz/anchor";
+import type { Errors } from "../target/types/errors";
+
+// Configure the client to use the local cluster
+anchor.setProvider(anchor.AnchorProvider.env());
+
+const program = anchor.workspace.Errors as anchor.Program<Errors>;
+
+const [configPDA] = anchor.web3.PublicKey.findProgramAddressSync(
+  [Buffer.from("config")],
+  program.programId
+);
+
+// migrateAccount
+for (const name of ["machine", "order", "reward", "rewardMachine", "task"]) {
+  const accountClient = program.account[name];
+  // Fetch raw accounts, older layouts can not be decoded with the current IDL
+  const accounts = await program.provider.connection.getProgramAccounts(
+    program.programId,
+    { filters: [{ memcmp: accountClient.coder.accounts.memcmp(accountClient.idlAccount.name) }] }
+  );
+  for (const account of accounts) {
+    // Accounts with the current layout size are already migrated
+    if (account.account.data.length >= accountClient.size) {
+      continue;
+    }
+    const txHash = await program.methods
+      .migrateAccount()
+      .accounts({
+        config: configPDA,
+        account: account.pubkey,
+      })
+      .rpc();
+    await logTransaction(txHash);
+  }
+}
+
+// logTransaction
+async function logTransaction(txHash) {
+  const { blockhash, lastValidBlockHeight } =
+    await program.provider.connection.getLatestBlockhash();
+
+  await program.provider.connection.confirmTransaction({
+    blockhash,
+    lastValidBlockHeight,
+    signature: txHash,
+  });
+
+  console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+}
@@ -0,0 +1,59 @@
+import * as anchor from "@coral-xyz/anchor";
+import type { Errors } from "../target/types/errors";
+
+// Configure the client to use the local cluster
+anchor.setProvider(anchor.AnchorProvider.env());
+
+const program = anchor.workspace.Errors as anchor.Program<Errors>;
+
+const [configPDA] = anchor.web3.PublicKey.findProgramAddressSync(
+  [Buffer.from("config")],
+  program.programId
+);
+
+// migrateEscrow, run after migrating the order accounts with account.ts
+const orders = await program.account.order.all();
+for (const order of orders) {
+  const status = order.account.status;
+  if (!("preparing" in status) && !("training" in status)) {
+    continue;
+  }
+  const [escrowPDA] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("escrow"), order.publicKey.toBytes()],
+    program.programId
+  );
+  // Orders placed after the upgrade already have an escrow
+  if (await program.provider.connection.getAccountInfo(escrowPDA)) {
+    continue;
+  }
+  const [vaultPDA] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("vault"), order.account.mint.toBytes()],
+    program.programId
+  );
+  const txHash = await program.methods
+    .migrateEscrow()
+    .accounts({
+      config: configPDA,
+      order: order.publicKey,
+      escrow: escrowPDA,
+      vault: vaultPDA,
+      mint: order.account.mint,
+      tokenProgram: anchor.utils.token.TOKEN_PROGRAM_ID,
+    })
+    .rpc();
+  await logTransaction(txHash);
+}
+
+// logTransaction
+async function logTransaction(txHash) {
+  const { blockhash, lastValidBlockHeight } =
+    await program.provider.connection.getLatestBlockhash();
+
+  await program.provider.connection.confirmTransaction({
+    blockhash,
+    lastValidBlockHeight,
+    signature: txHash,
+  });
+
+  console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+}
@@ -8,5 +8,73 @@ module.exports = async function (provider) {
   // Configure client to use the provider.
   anchor.setProvider(provider);
 
-  // Add your deploy script here.
+  const program = anchor.workspace.DistriAi;
+  const [config] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  );
+  const [programData] = anchor.web3.PublicKey.findProgramAddressSync(
+    [program.programId.toBuffer()],
+    new anchor.web3.PublicKey("BPFLoaderUpgradeab1e11111111111111111111111")
+  );
+
+  // DIST token mint on devnet
+  const mint = new anchor.web3.PublicKey(
+    "896KfVVY6VRGQs1d9CKLnKUEgXXCCJcEEg7LwSK84vWE"
+  );
+  const params = {
+    // Period 0 start time: 2024-02-27 00:00:00 UTC
+    genesisTime: new anchor.BN(1708992000),
+    periodDuration: new anchor.BN(86400),
+    refundWindow: new anchor.BN(300),
+    billingPeriod: new anchor.BN(3600),
+    startTimeout: new anchor.BN(86400),
+    settleGracePeriod: new anchor.BN(86400),
+    // 5% protocol fee paid to the deployer
+    feeBps: 500,
+    treasury: provider.wallet.publicKey,
+    // Machines stake at least 1000 DIST, unbonding for 7 days
+    minStake: new anchor.BN(1_000_000_000_000),
+    unbondingPeriod: new anchor.BN(604800),
+    // Failed or expired orders slash 10% of the stake, half compensating the buyer
+    slashBps: 1000,
+    slashCompensationBps: 5000,
+    arbiter: provider.wallet.publicKey,
+    // Machines silent for 10 minutes can't be rented
+    heartbeatTimeout: new anchor.BN(600),
+    // Machines are verified by the deployer and need a score of 1 to earn rewards
+    oracle: provider.wallet.publicKey,
+    rewardMinScore: 1,
+    // Orders the seller never completes are settled half to the seller and half to the buyer
+    settleSellerBps: 5000,
+    // Buyers can dispute completed orders for a day, unresolved disputes are settled by the time used after a week
+    challengePeriod: new anchor.BN(86400),
+    disputeTimeout: new anchor.BN(604800),
+    // Reservations cancelled before their window pay 10% to the seller
+    cancelFeeBps: 1000,
+  };
+
+  await program.methods
+    .initialize(params)
+    .accounts({
+      config,
+      mint,
+      program: program.programId,
+      programData,
+    })
+    .rpc();
+
+  // Allow machine offers priced in DIST
+  const [paymentMint] = anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("payment-mint"), mint.toBuffer()],
+    program.programId
+  );
+  await program.methods
+    .addPaymentMint()
+    .accounts({
+      config,
+      paymentMint,
+      mint,
+    })
+    .rpc();
 };
@@ -12,4 +12,52 @@ pub enum DistriAIError {
     InvalidPeriod,
     /// Reward has been claimed.
     RepeatClaim,
+    /// The config parameters are invalid.
+    InvalidConfig,
+    /// The signer is not allowed to perform this operation.
+    Unauthorized,
+    /// The machine stake is below the required amount.
+    InsufficientStake,
+    /// The unbonding stake can't be withdrawn yet.
+    StakeUnbonding,
+    /// The machine still holds stake that must be withdrawn first.
+    StakeRemaining,
+    /// The rating is out of range.
+    InvalidRating,
+    /// The order has been rated.
+    RepeatRating,
+    /// The dispute split exceeds the order total.
+    InvalidSplit,
+    /// The billing granularity must be positive and divide the billing period.
+    InvalidBillingGranularity,
+    /// An arithmetic operation overflowed.
+    MathOverflow,
+    /// The duration is negative or out of range.
+    InvalidDuration,
+    /// The machine price exceeds the maximum price accepted by the buyer.
+    PriceTooHigh,
+    /// The machine owner is not the one expected by the buyer.
+    UnexpectedMachineOwner,
+    /// The machine does not have enough free capacity.
+    InsufficientCapacity,
+    /// The reservation overlaps bookings using the machine capacity.
+    ReservationConflict,
+    /// The machine calendar has no room for more bookings.
+    CalendarFull,
+    /// The reservation start time is invalid.
+    InvalidStartTime,
+    /// The machine has not sent a heartbeat recently.
+    MachineOffline,
+    /// The machine is not verified, staked or scored enough to earn rewards.
+    MachineNotEligible,
+    /// The reward period has been finalized.
+    PeriodFinalized,
+    /// The reward period has not been finalized yet.
+    PeriodNotFinalized,
+    /// The reward pool does not hold enough tokens for the finalized rewards.
+    InsufficientRewardPool,
+    /// The config field can not be changed after initialization.
+    ImmutableConfig,
+    /// The account layout version is newer than this program supports.
+    UnsupportedVersion,
 }
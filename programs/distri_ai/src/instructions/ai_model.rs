@@ -38,9 +38,10 @@ pub fn create_ai_model(
     ai_model.update_time = now_ts;
 
     // Emitting an event to log the creation of the AI model
-    emit!(AiModelEvent {
+    emit!(AiModelCreatedEvent {
         owner: ai_model.owner,
         name: ai_model.name.clone(),
+        timestamp: now_ts,
     });
     Ok(())
 }
@@ -50,9 +51,10 @@ pub fn remove_ai_model(ctx: Context<RemoveAiModel>) -> Result<()> {
     let ai_model = &mut ctx.accounts.ai_model;
 
     // Emit an event carrying the AI model's owner and name to log the removal action.
-    emit!(AiModelEvent {
+    emit!(AiModelRemovedEvent {
         owner: ai_model.owner,
         name: ai_model.name.clone(),
+        timestamp: Clock::get()?.unix_timestamp,
     });
     Ok(())
 }
@@ -97,9 +99,17 @@ pub struct RemoveAiModel<'info> {
     pub owner: Signer<'info>,
 }
 
-// Defines an event structure `AiModelEvent` to log events related to AI models.
+// Defines event structures `AiModelCreatedEvent` and `AiModelRemovedEvent` to log events related to AI models.
 #[event]
-pub struct AiModelEvent {
+pub struct AiModelCreatedEvent {
     pub owner: Pubkey,
     pub name: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AiModelRemovedEvent {
+    pub owner: Pubkey,
+    pub name: String,
+    pub timestamp: i64,
 }
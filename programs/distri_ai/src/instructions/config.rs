@@ -0,0 +1,226 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+use crate::errors::DistriAIError;
+use crate::program::DistriAi;
+use crate::state::config::*;
+use crate::state::payment_mint::*;
+
+/// Creates the program config, only the program upgrade authority can call it.
+pub fn initialize(ctx: Context<Initialize>, params: ConfigParams) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    config.admin = ctx.accounts.admin.key();
+    config.mint = ctx.accounts.mint.key();
+    set_params(config, params)?;
+
+    emit!(ConfigEvent {
+        admin: config.admin,
+        mint: config.mint,
+    });
+    Ok(())
+}
+
+/// Updates the program config, only the current admin can call it.
+///
+/// The mint, reward periods and billing period are fixed at initialization, since stake vaults,
+/// the reward pool, reward PDAs and open orders depend on them.
+pub fn update_config(
+    ctx: Context<UpdateConfig>,
+    new_admin: Pubkey,
+    params: ConfigParams,
+) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    require!(
+        params.genesis_time == config.genesis_time
+            && params.period_duration == config.period_duration
+            && params.billing_period == config.billing_period,
+        DistriAIError::ImmutableConfig
+    );
+    config.admin = new_admin;
+    set_params(config, params)?;
+
+    emit!(ConfigEvent {
+        admin: config.admin,
+        mint: config.mint,
+    });
+    Ok(())
+}
+
+/// Allows machine offers to be priced in a token mint, only the admin can call it.
+pub fn add_payment_mint(ctx: Context<AddPaymentMint>) -> Result<()> {
+    let payment_mint = &mut ctx.accounts.payment_mint;
+    payment_mint.mint = ctx.accounts.mint.key();
+
+    emit!(PaymentMintEvent {
+        mint: payment_mint.mint,
+    });
+    Ok(())
+}
+
+/// Disallows a token mint for new machine offers and orders, only the admin can call it.
+pub fn remove_payment_mint(ctx: Context<RemovePaymentMint>) -> Result<()> {
+    emit!(PaymentMintEvent {
+        mint: ctx.accounts.payment_mint.mint,
+    });
+    Ok(())
+}
+
+fn set_params(config: &mut Config, params: ConfigParams) -> Result<()> {
+    require_gt!(params.period_duration, 0, DistriAIError::InvalidConfig);
+    require_gte!(params.refund_window, 0, DistriAIError::InvalidConfig);
+    require_gt!(params.billing_period, 0, DistriAIError::InvalidConfig);
+    require_gte!(params.start_timeout, params.refund_window, DistriAIError::InvalidConfig);
+    require_gte!(params.settle_grace_period, 0, DistriAIError::InvalidConfig);
+    require_gte!(
+        Config::BPS_DENOMINATOR,
+        u64::from(params.fee_bps),
+        DistriAIError::InvalidConfig
+    );
+    require_gte!(params.unbonding_period, 0, DistriAIError::InvalidConfig);
+    require_gt!(params.heartbeat_timeout, 0, DistriAIError::InvalidConfig);
+    // Rewarded machines must have a positive weight to share a weighted period
+    require_gte!(params.reward_min_score, 1, DistriAIError::InvalidConfig);
+    require_gte!(
+        Config::BPS_DENOMINATOR,
+        u64::from(params.slash_bps),
+        DistriAIError::InvalidConfig
+    );
+    require_gte!(
+        Config::BPS_DENOMINATOR,
+        u64::from(params.slash_compensation_bps),
+        DistriAIError::InvalidConfig
+    );
+    require_gte!(
+        Config::BPS_DENOMINATOR,
+        u64::from(params.settle_seller_bps),
+        DistriAIError::InvalidConfig
+    );
+    require_gte!(params.challenge_period, 0, DistriAIError::InvalidConfig);
+    require_gt!(params.dispute_timeout, 0, DistriAIError::InvalidConfig);
+    require_gte!(
+        Config::BPS_DENOMINATOR,
+        u64::from(params.cancel_fee_bps),
+        DistriAIError::InvalidConfig
+    );
+
+    config.genesis_time = params.genesis_time;
+    config.period_duration = params.period_duration;
+    config.refund_window = params.refund_window;
+    config.billing_period = params.billing_period;
+    config.start_timeout = params.start_timeout;
+    config.settle_grace_period = params.settle_grace_period;
+    config.fee_bps = params.fee_bps;
+    config.treasury = params.treasury;
+    config.min_stake = params.min_stake;
+    config.unbonding_period = params.unbonding_period;
+    config.slash_bps = params.slash_bps;
+    config.slash_compensation_bps = params.slash_compensation_bps;
+    config.arbiter = params.arbiter;
+    config.heartbeat_timeout = params.heartbeat_timeout;
+    config.oracle = params.oracle;
+    config.reward_min_score = params.reward_min_score;
+    config.settle_seller_bps = params.settle_seller_bps;
+    config.challenge_period = params.challenge_period;
+    config.dispute_timeout = params.dispute_timeout;
+    config.cancel_fee_bps = params.cancel_fee_bps;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        seeds = [b"config"],
+        bump,
+        payer = admin,
+        space = 8 + Config::INIT_SPACE
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        constraint = program.programdata_address()? == Some(program_data.key())
+    )]
+    pub program: Program<'info, DistriAi>,
+
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, Config>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AddPaymentMint<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init,
+        seeds = [b"payment-mint", mint.key().as_ref()],
+        bump,
+        payer = admin,
+        space = 8 + PaymentMint::INIT_SPACE
+    )]
+    pub payment_mint: Account<'info, PaymentMint>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RemovePaymentMint<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump,
+        has_one = admin
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"payment-mint", payment_mint.mint.as_ref()],
+        bump,
+        close = admin
+    )]
+    pub payment_mint: Account<'info, PaymentMint>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+#[event]
+pub struct ConfigEvent {
+    pub admin: Pubkey,
+    pub mint: Pubkey,
+}
+
+#[event]
+pub struct PaymentMintEvent {
+    pub mint: Pubkey,
+}
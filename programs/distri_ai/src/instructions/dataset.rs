@@ -35,9 +35,10 @@ pub fn create_dataset(
     dataset.create_time = now_ts;
     dataset.update_time = now_ts;
 
-    emit!(DatasetEvent {
+    emit!(DatasetCreatedEvent {
         owner: dataset.owner,
         name: dataset.name.clone(),
+        timestamp: now_ts,
     });
     Ok(())
 }
@@ -46,9 +47,10 @@ pub fn create_dataset(
 pub fn remove_dataset(ctx: Context<RemoveDataset>) -> Result<()> {
     let dataset = &mut ctx.accounts.dataset;
 
-    emit!(DatasetEvent {
+    emit!(DatasetRemovedEvent {
         owner: dataset.owner,
         name: dataset.name.clone(),
+        timestamp: Clock::get()?.unix_timestamp,
     });
     Ok(())
 }
@@ -92,7 +94,15 @@ pub struct RemoveDataset<'info> {
 }
 
 #[event]
-pub struct DatasetEvent {
+pub struct DatasetCreatedEvent {
     pub owner: Pubkey,
     pub name: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DatasetRemovedEvent {
+    pub owner: Pubkey,
+    pub name: String,
+    pub timestamp: i64,
 }
@@ -0,0 +1,527 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{Mint, TokenAccount, TokenInterface},
+};
+use crate::errors::DistriAIError;
+use crate::instructions::order::{pay_out_escrow, PayoutAccounts};
+use crate::state::config::*;
+use crate::state::dispute::*;
+use crate::state::machine::*;
+use crate::state::order::*;
+
+/// Opens a dispute on an active order, or on a delivered order during its challenge period,
+/// freezing its funds until the arbiter resolves it.
+pub fn open_dispute(ctx: Context<OpenDispute>, evidence: String) -> Result<()> {
+    require_gte!(
+        Dispute::EVIDENCE_MAX_LENGTH,
+        evidence.len(),
+        DistriAIError::StringTooLong
+    );
+
+    let opener = ctx.accounts.signer.key();
+    let order = &mut ctx.accounts.order;
+    require!(
+        opener == order.buyer || opener == order.seller,
+        DistriAIError::Unauthorized
+    );
+    let now_ts = Clock::get()?.unix_timestamp;
+    let disputable = match order.status {
+        OrderStatus::Preparing | OrderStatus::Training => true,
+        OrderStatus::Delivered => {
+            now_ts < order.challenge_end_time(ctx.accounts.config.challenge_period)?
+        }
+        _ => false,
+    };
+    require!(disputable, DistriAIError::IncorrectStatus);
+    let old_status = order.status.clone();
+    order.status = OrderStatus::Disputed;
+
+    let dispute = &mut ctx.accounts.dispute;
+    dispute.order = order.key();
+    dispute.opener = opener;
+    dispute.order_status = old_status.clone();
+    if opener == order.buyer {
+        dispute.buyer_evidence = evidence;
+    } else {
+        dispute.seller_evidence = evidence;
+    }
+    dispute.open_time = now_ts;
+
+    emit!(DisputeOpenedEvent {
+        order_id: order.order_id,
+        buyer: order.buyer,
+        seller: order.seller,
+        machine_id: order.machine_id,
+        old_status,
+        new_status: order.status.clone(),
+        opener,
+        timestamp: now_ts,
+    });
+    Ok(())
+}
+
+/// Records the evidence URI of the buyer or seller of a disputed order.
+pub fn submit_evidence(ctx: Context<SubmitEvidence>, evidence: String) -> Result<()> {
+    require_gte!(
+        Dispute::EVIDENCE_MAX_LENGTH,
+        evidence.len(),
+        DistriAIError::StringTooLong
+    );
+
+    let submitter = ctx.accounts.signer.key();
+    let order = &ctx.accounts.order;
+    require!(
+        order.status == OrderStatus::Disputed,
+        DistriAIError::IncorrectStatus
+    );
+    let dispute = &mut ctx.accounts.dispute;
+    if submitter == order.buyer {
+        dispute.buyer_evidence = evidence.clone();
+    } else if submitter == order.seller {
+        dispute.seller_evidence = evidence.clone();
+    } else {
+        return err!(DistriAIError::Unauthorized);
+    }
+
+    emit!(DisputeEvidenceSubmittedEvent {
+        order_id: order.order_id,
+        submitter,
+        evidence,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Resolves a dispute by splitting the order funds, only the arbiter can call it.
+///
+/// The order is `Failed` if the buyer gets more than the seller, otherwise it is `Completed`.
+pub fn resolve_dispute(ctx: Context<ResolveDispute>, buyer_amount: u64) -> Result<()> {
+    let order_key = ctx.accounts.order.key();
+    let order = &mut ctx.accounts.order;
+    require!(
+        order.status == OrderStatus::Disputed,
+        DistriAIError::IncorrectStatus
+    );
+    require_gte!(order.total, buyer_amount, DistriAIError::InvalidSplit);
+    let old_status = order.status.clone();
+    let used_total = order.total - buyer_amount;
+
+    let machine = &mut ctx.accounts.machine;
+    // A delivered order already released its capacity
+    if ctx.accounts.dispute.order_status != OrderStatus::Delivered {
+        machine.release(order.units)?;
+    }
+    machine.close_order();
+    if buyer_amount > used_total {
+        order.status = OrderStatus::Failed;
+        machine.failed_count = machine.failed_count.saturating_add(1);
+    } else {
+        order.status = OrderStatus::Completed;
+        machine.completed_count = machine.completed_count.saturating_add(1);
+    }
+
+    // Split the escrow, the protocol fee is taken from the seller share
+    let signer: &[&[&[u8]]] = &[&[b"escrow", order_key.as_ref(), &[ctx.bumps.escrow]]];
+    let (seller_amount, _, fee) = pay_out_escrow(
+        &ctx.accounts.config,
+        PayoutAccounts {
+            token_program: &ctx.accounts.token_program,
+            escrow: &ctx.accounts.escrow,
+            mint: &ctx.accounts.mint,
+            seller_ata: ctx.accounts.seller_ata.to_account_info(),
+            treasury_ata: ctx.accounts.treasury_ata.to_account_info(),
+            buyer_ata: Some(ctx.accounts.buyer_ata.to_account_info()),
+            buyer: ctx.accounts.buyer.to_account_info(),
+        },
+        signer,
+        order.total,
+        used_total,
+    )?;
+
+    emit!(DisputeResolvedEvent {
+        order_id: order.order_id,
+        buyer: order.buyer,
+        seller: order.seller,
+        machine_id: order.machine_id,
+        old_status,
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        seller_amount,
+        buyer_amount,
+        fee,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Settles a dispute the arbiter did not resolve before the dispute timeout, anyone can call it.
+///
+/// The seller is paid for the time billed before the dispute was opened, like a refunded
+/// training order, and the buyer is refunded the rest. A preparing order is refunded in full
+/// and a delivered order is paid in full.
+pub fn expire_dispute(ctx: Context<ExpireDispute>) -> Result<()> {
+    let order_key = ctx.accounts.order.key();
+    let order = &mut ctx.accounts.order;
+    require!(
+        order.status == OrderStatus::Disputed,
+        DistriAIError::IncorrectStatus
+    );
+    let now_ts = Clock::get()?.unix_timestamp;
+    let deadline = ctx
+        .accounts
+        .dispute
+        .open_time
+        .checked_add(ctx.accounts.config.dispute_timeout)
+        .ok_or(error!(DistriAIError::MathOverflow))?;
+    require_gte!(now_ts, deadline, DistriAIError::IncorrectStatus);
+    let old_status = order.status.clone();
+    order.status = OrderStatus::Refunded;
+    order.refund_time = now_ts;
+
+    // A delivered order already released its capacity
+    let machine = &mut ctx.accounts.machine;
+    if ctx.accounts.dispute.order_status != OrderStatus::Delivered {
+        machine.release(order.units)?;
+    }
+    machine.close_order();
+
+    // Bill the time used before the dispute, a preparing order has not started
+    let billing_period = ctx.accounts.config.billing_period;
+    let used_total = if ctx.accounts.dispute.order_status == OrderStatus::Preparing {
+        0
+    } else {
+        let order_seconds = Order::duration_seconds(order.duration, billing_period)?;
+        let used_seconds = ctx
+            .accounts
+            .dispute
+            .open_time
+            .checked_sub(order.start_time)
+            .ok_or(DistriAIError::MathOverflow)?
+            .min(order_seconds);
+        Order::billed_amount(
+            order.price,
+            order.units,
+            order.billing_granularity,
+            billing_period,
+            used_seconds,
+        )?
+        .min(order.total)
+    };
+
+    // Split the escrow, the protocol fee is taken from the seller share
+    let signer: &[&[&[u8]]] = &[&[b"escrow", order_key.as_ref(), &[ctx.bumps.escrow]]];
+    let (seller_amount, buyer_amount, fee) = pay_out_escrow(
+        &ctx.accounts.config,
+        PayoutAccounts {
+            token_program: &ctx.accounts.token_program,
+            escrow: &ctx.accounts.escrow,
+            mint: &ctx.accounts.mint,
+            seller_ata: ctx.accounts.seller_ata.to_account_info(),
+            treasury_ata: ctx.accounts.treasury_ata.to_account_info(),
+            buyer_ata: Some(ctx.accounts.buyer_ata.to_account_info()),
+            buyer: ctx.accounts.buyer.to_account_info(),
+        },
+        signer,
+        order.total,
+        used_total,
+    )?;
+
+    emit!(DisputeExpiredEvent {
+        order_id: order.order_id,
+        buyer: order.buyer,
+        seller: order.seller,
+        machine_id: order.machine_id,
+        old_status,
+        new_status: order.status.clone(),
+        seller_amount,
+        buyer_amount,
+        fee,
+        timestamp: now_ts,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct OpenDispute<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(mut)]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        init,
+        seeds = [b"dispute", order.key().as_ref()],
+        bump,
+        payer = signer,
+        space = 8 + Dispute::INIT_SPACE
+    )]
+    pub dispute: Box<Account<'info, Dispute>>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitEvidence<'info> {
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [b"dispute", order.key().as_ref()],
+        bump
+    )]
+    pub dispute: Box<Account<'info, Dispute>>,
+
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveDispute<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump,
+        has_one = arbiter
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(mut)]
+    pub arbiter: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = machine.uuid == order.machine_id && machine.owner == order.seller
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    #[account(mut)]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [b"dispute", order.key().as_ref()],
+        bump,
+        has_one = opener,
+        close = opener
+    )]
+    pub dispute: Box<Account<'info, Dispute>>,
+
+    /// CHECK: Receives the rent of the closed dispute.
+    #[account(mut)]
+    pub opener: UncheckedAccount<'info>,
+
+    /// CHECK: Receives the rent of the closed escrow.
+    #[account(
+        mut,
+        address = order.buyer
+    )]
+    pub buyer: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = arbiter,
+        associated_token::mint = mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Owner of the seller token account.
+    #[account(
+        address = order.seller
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = arbiter,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Owner of the treasury token account.
+    #[account(
+        address = config.treasury
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = arbiter,
+        associated_token::mint = mint,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program
+    )]
+    pub treasury_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", order.key().as_ref()],
+        bump
+    )]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        address = order.mint
+    )]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireDispute<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        constraint = machine.uuid == order.machine_id && machine.owner == order.seller
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    #[account(mut)]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        mut,
+        seeds = [b"dispute", order.key().as_ref()],
+        bump,
+        has_one = opener,
+        close = opener
+    )]
+    pub dispute: Box<Account<'info, Dispute>>,
+
+    /// CHECK: Receives the rent of the closed dispute.
+    #[account(mut)]
+    pub opener: UncheckedAccount<'info>,
+
+    /// CHECK: Receives the rent of the closed escrow.
+    #[account(
+        mut,
+        address = order.buyer
+    )]
+    pub buyer: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Owner of the seller token account.
+    #[account(
+        address = order.seller
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Owner of the treasury token account.
+    #[account(
+        address = config.treasury
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program
+    )]
+    pub treasury_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", order.key().as_ref()],
+        bump
+    )]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        address = order.mint
+    )]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct DisputeOpenedEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub opener: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DisputeEvidenceSubmittedEvent {
+    pub order_id: [u8; 16],
+    pub submitter: Pubkey,
+    pub evidence: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DisputeResolvedEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    pub seller_amount: u64,
+    pub buyer_amount: u64,
+    /// The protocol fee sent to the treasury.
+    pub fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct DisputeExpiredEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub seller_amount: u64,
+    pub buyer_amount: u64,
+    /// The protocol fee sent to the treasury.
+    pub fee: u64,
+    pub timestamp: i64,
+}
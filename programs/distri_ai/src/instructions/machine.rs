@@ -1,6 +1,10 @@
 use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
 use crate::errors::DistriAIError;
+use crate::state::config::*;
 use crate::state::machine::*;
+use crate::state::payment_mint::*;
+use crate::state::Versioned;
 
 /// Adds a new machine to the smart contract with the given UUID and metadata.
 pub fn add_machine(ctx: Context<AddMachine>, uuid: [u8; 16], metadata: String) -> Result<()> {
@@ -17,11 +21,14 @@ pub fn add_machine(ctx: Context<AddMachine>, uuid: [u8; 16], metadata: String) -
     machine.uuid = uuid;
     machine.metadata = metadata;
     machine.status = MachineStatus::Idle;
-    
-    // Emit a `MachineEvent` event with the owner and UUID of the newly created machine
-    emit!(MachineEvent {
+    machine.version = Machine::VERSION;
+
+    // Emit a `MachineAddedEvent` event with the owner and UUID of the newly created machine
+    emit!(MachineAddedEvent {
         owner: machine.owner,
         uuid: machine.uuid,
+        metadata: machine.metadata.clone(),
+        timestamp: Clock::get()?.unix_timestamp,
     });
     Ok(())
 }
@@ -32,13 +39,21 @@ pub fn remove_machine(ctx: Context<RemoveMachine>) -> Result<()> {
     //  Declares a mutable reference to the `machine` object within the `accounts` field of the provided context `ctx`.
     let machine = &mut ctx.accounts.machine;
     require!(
-        machine.status != MachineStatus::Renting,
+        machine.status != MachineStatus::Renting && machine.allocated == 0,
         DistriAIError::IncorrectStatus
     );
+    // Settling an order needs its machine, so orders must be paid out first
+    require_eq!(machine.open_orders, 0, DistriAIError::IncorrectStatus);
+    require!(
+        machine.stake == 0 && machine.unbonding_stake == 0,
+        DistriAIError::StakeRemaining
+    );
 
-    emit!(MachineEvent {
+    emit!(MachineRemovedEvent {
         owner: machine.owner,
         uuid: machine.uuid,
+        old_status: machine.status.clone(),
+        timestamp: Clock::get()?.unix_timestamp,
     });
     Ok(())
 }
@@ -46,26 +61,67 @@ pub fn remove_machine(ctx: Context<RemoveMachine>) -> Result<()> {
 // Define a public function `make_offer` to make an offer for renting a machine.
 // Parameters:
 // ctx - the program context, which includes access to the machine account
-// price - the price offered for renting the machine as a 64-bit unsigned integer
+// price - the price offered for renting the machine per hour, in units of the chosen payment mint
 // max_duration - the maximum duration for the rental as a 32-bit unsigned integer
 // disk - the disk space required for the rental as a 32-bit unsigned integer
 // Returns:
 // Result<()> - an Ok result if the operation is successful, or an Err result with an error if not
-pub fn make_offer(ctx: Context<MakeOffer>, price: u64, max_duration: u32, disk: u32) -> Result<()> {
+pub fn make_offer(
+    ctx: Context<MakeOffer>,
+    price: u64,
+    max_duration: u32,
+    disk: u32,
+    billing_granularity: u32,
+    capacity: u32,
+) -> Result<()> {
     let machine = &mut ctx.accounts.machine;
     require!(
         machine.status == MachineStatus::Idle,
         DistriAIError::IncorrectStatus
     );
+    require_gte!(
+        machine.stake,
+        ctx.accounts.config.min_stake,
+        DistriAIError::InsufficientStake
+    );
+    require!(
+        billing_granularity > 0
+            && ctx.accounts.config.billing_period % i64::from(billing_granularity) == 0,
+        DistriAIError::InvalidBillingGranularity
+    );
+    // Orders placed before the offer was cancelled may still hold capacity
+    require!(
+        capacity > 0 && capacity >= machine.allocated,
+        DistriAIError::InsufficientCapacity
+    );
 
-    machine.status = MachineStatus::ForRent;
+    let old_status = machine.status.clone();
+    machine.status = if capacity == machine.allocated {
+        MachineStatus::Renting
+    } else {
+        MachineStatus::ForRent
+    };
+    machine.mint = ctx.accounts.mint.key();
     machine.price = price;
     machine.max_duration = max_duration;
     machine.disk = disk;
+    machine.billing_granularity = billing_granularity;
+    machine.capacity = capacity;
+    machine.last_seen = Clock::get()?.unix_timestamp;
+    machine.pending_offer = None;
 
-    emit!(MachineEvent {
+    emit!(MachineOfferedEvent {
         owner: machine.owner,
         uuid: machine.uuid,
+        old_status,
+        new_status: machine.status.clone(),
+        mint: machine.mint,
+        price: machine.price,
+        max_duration: machine.max_duration,
+        disk: machine.disk,
+        billing_granularity: machine.billing_granularity,
+        capacity: machine.capacity,
+        timestamp: Clock::get()?.unix_timestamp,
     });
     Ok(())
 }
@@ -78,11 +134,131 @@ pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
         DistriAIError::IncorrectStatus
     );
 
-    emit!(MachineEvent {
+    let old_status = machine.status.clone();
+    machine.status = MachineStatus::Idle;
+    machine.pending_offer = None;
+
+    emit!(MachineOfferCancelledEvent {
         owner: machine.owner,
         uuid: machine.uuid,
+        old_status,
+        new_status: machine.status.clone(),
+        timestamp: Clock::get()?.unix_timestamp,
     });
-    machine.status = MachineStatus::Idle;
+    Ok(())
+}
+
+/// Sets the verification flag and score of a machine, only the admin or the oracle can call it.
+pub fn verify_machine(ctx: Context<VerifyMachine>, verified: bool, score: u8) -> Result<()> {
+    let config = &ctx.accounts.config;
+    let verifier = ctx.accounts.verifier.key();
+    require!(
+        verifier == config.admin || verifier == config.oracle,
+        DistriAIError::Unauthorized
+    );
+
+    let machine = &mut ctx.accounts.machine;
+    machine.verified = verified;
+    machine.score = score;
+
+    emit!(MachineVerifiedEvent {
+        owner: machine.owner,
+        uuid: machine.uuid,
+        verifier,
+        verified,
+        score,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Records that a machine is online, only the owner can call it.
+///
+/// The pending offer is applied too once effective, so the listed terms stay current.
+pub fn heartbeat(ctx: Context<Heartbeat>) -> Result<()> {
+    let now_ts = Clock::get()?.unix_timestamp;
+    let machine = &mut ctx.accounts.machine;
+    machine.last_seen = now_ts;
+    machine.apply_pending_offer(now_ts);
+
+    emit!(MachineHeartbeatEvent {
+        owner: machine.owner,
+        uuid: machine.uuid,
+        last_seen: machine.last_seen,
+    });
+    Ok(())
+}
+
+/// Updates the metadata and hardware spec of a machine without active orders.
+pub fn update_machine_metadata(
+    ctx: Context<UpdateMachineMetadata>,
+    metadata: String,
+    spec: MachineSpec,
+) -> Result<()> {
+    require_gte!(
+        Machine::METADATA_MAX_LENGTH,
+        metadata.len(),
+        DistriAIError::StringTooLong
+    );
+
+    let machine = &mut ctx.accounts.machine;
+    require!(
+        machine.status != MachineStatus::Renting && machine.allocated == 0,
+        DistriAIError::IncorrectStatus
+    );
+    machine.metadata = metadata;
+    machine.spec = spec;
+
+    emit!(MachineMetadataUpdatedEvent {
+        owner: machine.owner,
+        uuid: machine.uuid,
+        metadata: machine.metadata.clone(),
+        spec: machine.spec.clone(),
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Updates the terms of an offered machine without delisting it,
+/// either immediately or from `effective_time` on.
+pub fn update_offer(
+    ctx: Context<UpdateOffer>,
+    price: u64,
+    max_duration: u32,
+    disk: u32,
+    effective_time: Option<i64>,
+) -> Result<()> {
+    let machine = &mut ctx.accounts.machine;
+    require!(
+        machine.status == MachineStatus::ForRent,
+        DistriAIError::IncorrectStatus
+    );
+
+    let now_ts = Clock::get()?.unix_timestamp;
+    machine.apply_pending_offer(now_ts);
+    let offer = PendingOffer {
+        price,
+        max_duration,
+        disk,
+        effective_time: effective_time.unwrap_or(now_ts).max(now_ts),
+    };
+
+    emit!(MachineOfferUpdatedEvent {
+        owner: machine.owner,
+        uuid: machine.uuid,
+        old_price: machine.price,
+        new_price: offer.price,
+        old_max_duration: machine.max_duration,
+        new_max_duration: offer.max_duration,
+        old_disk: machine.disk,
+        new_disk: offer.disk,
+        effective_time: offer.effective_time,
+        timestamp: now_ts,
+    });
+
+    // A new update replaces any update still pending
+    machine.pending_offer = Some(offer);
+    machine.apply_pending_offer(now_ts);
     Ok(())
 }
 
@@ -125,6 +301,12 @@ pub struct RemoveMachine<'info> {
 
 #[derive(Accounts)]
 pub struct MakeOffer<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
     #[account(
         mut,
         has_one = owner
@@ -133,6 +315,62 @@ pub struct MakeOffer<'info> {
 
     #[account(mut)]
     pub owner: Signer<'info>,
+
+    // The payment mint must be allowed by the admin
+    #[account(
+        seeds = [b"payment-mint", mint.key().as_ref()],
+        bump
+    )]
+    pub payment_mint: Account<'info, PaymentMint>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyMachine<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(mut)]
+    pub machine: Box<Account<'info, Machine>>,
+
+    pub verifier: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Heartbeat<'info> {
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub machine: Account<'info, Machine>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMachineMetadata<'info> {
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateOffer<'info> {
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    pub owner: Signer<'info>,
 }
 
 #[derive(Accounts)]
@@ -148,7 +386,81 @@ pub struct CancelOffer<'info> {
 }
 
 #[event]
-pub struct MachineEvent {
+pub struct MachineAddedEvent {
+    pub owner: Pubkey,
+    pub uuid: [u8; 16],
+    pub metadata: String,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MachineRemovedEvent {
+    pub owner: Pubkey,
+    pub uuid: [u8; 16],
+    pub old_status: MachineStatus,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MachineOfferedEvent {
+    pub owner: Pubkey,
+    pub uuid: [u8; 16],
+    pub old_status: MachineStatus,
+    pub new_status: MachineStatus,
+    pub mint: Pubkey,
+    pub price: u64,
+    pub max_duration: u32,
+    pub disk: u32,
+    pub billing_granularity: u32,
+    pub capacity: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MachineVerifiedEvent {
+    pub owner: Pubkey,
+    pub uuid: [u8; 16],
+    pub verifier: Pubkey,
+    pub verified: bool,
+    pub score: u8,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MachineHeartbeatEvent {
+    pub owner: Pubkey,
+    pub uuid: [u8; 16],
+    pub last_seen: i64,
+}
+
+#[event]
+pub struct MachineMetadataUpdatedEvent {
+    pub owner: Pubkey,
+    pub uuid: [u8; 16],
+    pub metadata: String,
+    pub spec: MachineSpec,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MachineOfferUpdatedEvent {
+    pub owner: Pubkey,
+    pub uuid: [u8; 16],
+    pub old_price: u64,
+    pub new_price: u64,
+    pub old_max_duration: u32,
+    pub new_max_duration: u32,
+    pub old_disk: u32,
+    pub new_disk: u32,
+    pub effective_time: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MachineOfferCancelledEvent {
     pub owner: Pubkey,
     pub uuid: [u8; 16],
+    pub old_status: MachineStatus,
+    pub new_status: MachineStatus,
+    pub timestamp: i64,
 }
@@ -1,13 +1,21 @@
 pub use ai_model::*;
+pub use config::*;
 pub use dataset::*;
+pub use dispute::*;
 pub use machine::*;
 pub use order::*;
+pub use reservation::*;
 pub use reward::*;
+pub use stake::*;
 pub use task::*;
 
 pub mod ai_model;
+pub mod config;
 pub mod dataset;
+pub mod dispute;
 pub mod machine;
 pub mod order;
+pub mod reservation;
 pub mod reward;
+pub mod stake;
 pub mod task;
@@ -1,19 +1,37 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::program::invoke;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked},
+    token_2022::spl_token_2022::{
+        self,
+        extension::{
+            transfer_fee::{instruction::harvest_withheld_tokens_to_mint, TransferFeeAmount},
+            BaseStateWithExtensions, StateWithExtensions,
+        },
+    },
+    token_interface::{
+        close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
+        TransferChecked,
+    },
 };
-use crate::dist_token;
 use crate::errors::DistriAIError;
+use crate::instructions::stake::{slash_stake, SlashAccounts};
+use crate::state::calendar::*;
+use crate::state::config::*;
 use crate::state::machine::*;
 use crate::state::order::*;
+use crate::state::payment_mint::*;
+use crate::state::Versioned;
 
 /// Places an order to rent a machine, handling payment and updating machine status.
 pub fn place_order(
     ctx: Context<PlaceOrder>,
     order_id: [u8; 16],
     duration: u32,
+    units: u32,
     metadata: String,
+    max_price: u64,
+    expected_machine_owner: Pubkey,
 ) -> Result<()> {
     require_gte!(
         Order::METADATA_MAX_LENGTH,
@@ -21,30 +39,76 @@ pub fn place_order(
         DistriAIError::StringTooLong
     );
 
+    let now_ts = Clock::get()?.unix_timestamp;
+    let machine_key = ctx.accounts.machine.key();
     let machine = &mut ctx.accounts.machine;
     require!(
         machine.status == MachineStatus::ForRent,
         DistriAIError::IncorrectStatus
     );
+    require!(
+        machine.is_online(now_ts, ctx.accounts.config.heartbeat_timeout),
+        DistriAIError::MachineOffline
+    );
+    // Machines migrated from before staking may be offered without a stake to slash
+    require_gte!(
+        machine.stake,
+        ctx.accounts.config.min_stake,
+        DistriAIError::InsufficientStake
+    );
+    machine.apply_pending_offer(now_ts);
+    // Guard against the offer changing between signing and execution
+    require_keys_eq!(
+        machine.owner,
+        expected_machine_owner,
+        DistriAIError::UnexpectedMachineOwner
+    );
+    require_gte!(max_price, machine.price, DistriAIError::PriceTooHigh);
+    require_gt!(duration, 0, DistriAIError::InvalidDuration);
     require_gte!(
         machine.max_duration,
         duration,
         DistriAIError::DurationTooMuch
     );
 
-    // Transfer token from buyer to vault
-    let total = machine.price.saturating_mul(duration.into());
+    // Capacity booked by reservations starting during this order can't be taken
+    let billing_period = ctx.accounts.config.billing_period;
+    let order_seconds = Order::duration_seconds(duration, billing_period)?;
+    let order_end_time = now_ts
+        .checked_add(order_seconds)
+        .ok_or(DistriAIError::MathOverflow)?;
+    let calendar = &mut ctx.accounts.calendar;
+    calendar.machine = machine_key;
+    let free = machine.capacity.saturating_sub(machine.allocated);
+    require_gte!(
+        u64::from(free),
+        calendar.booked_units(now_ts, order_end_time) + u64::from(units),
+        DistriAIError::ReservationConflict
+    );
+
+    // Transfer token from buyer to escrow
+    let total = Order::billed_amount(
+        machine.price,
+        units,
+        machine.billing_granularity,
+        billing_period,
+        order_seconds,
+    )?;
     let cpi_context = CpiContext::new(
         ctx.accounts.token_program.to_account_info(),
         TransferChecked {
             from: ctx.accounts.buyer_ata.to_account_info(),
             mint: ctx.accounts.mint.to_account_info(),
-            to: ctx.accounts.vault.to_account_info(),
+            to: ctx.accounts.escrow.to_account_info(),
             authority: ctx.accounts.buyer.to_account_info(),
         },
     );
     transfer_checked(cpi_context, total, ctx.accounts.mint.decimals)?;
 
+    // Record the amount actually received, transfer fees are paid by the buyer
+    ctx.accounts.escrow.reload()?;
+    let total = ctx.accounts.escrow.amount;
+
     let order = &mut ctx.accounts.order;
     order.order_id = order_id;
     order.buyer = ctx.accounts.buyer.key();
@@ -55,30 +119,60 @@ pub fn place_order(
     order.total = total;
     order.metadata = metadata;
     order.status = OrderStatus::Preparing;
-    order.order_time = Clock::get()?.unix_timestamp;
+    order.order_time = now_ts;
     order.refund_time = 0;
-
-    machine.status = MachineStatus::Renting;
+    order.version = Order::VERSION;
+    order.mint = machine.mint;
+    order.billing_granularity = machine.billing_granularity;
+    order.units = units;
+    order.reserved_time = 0;
+
+    machine.allocate(units)?;
+    machine.open_order()?;
     machine.order_pda = order.key();
 
-    emit!(OrderEvent {
+    emit!(OrderPlacedEvent {
         order_id: order.order_id,
         buyer: order.buyer,
         seller: order.seller,
         machine_id: order.machine_id,
+        mint: order.mint,
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        units: order.units,
+        paid_amount: order.total,
+        timestamp: now_ts,
     });
     Ok(())
 }
 
 // RenewOrder renews an existing order by extending its duration and updating the total price.
-pub fn renew_order(ctx: Context<RenewOrder>, duration: u32) -> Result<()> {
-    let order = &mut ctx.accounts.order;
+// Renewals are charged at the price the order was placed at, not the current machine price.
+pub fn renew_order(
+    ctx: Context<RenewOrder>,
+    duration: u32,
+    max_price: u64,
+    expected_machine_owner: Pubkey,
+) -> Result<()> {
+    let order = &ctx.accounts.order;
     require!(
         order.status == OrderStatus::Training,
         DistriAIError::IncorrectStatus
     );
-
-    let new_duration = order.duration.saturating_add(duration);
+    require_keys_eq!(
+        order.seller,
+        expected_machine_owner,
+        DistriAIError::UnexpectedMachineOwner
+    );
+    require_gte!(max_price, order.price, DistriAIError::PriceTooHigh);
+
+    require_gt!(duration, 0, DistriAIError::InvalidDuration);
+    let new_duration = order
+        .duration
+        .checked_add(duration)
+        .ok_or(DistriAIError::InvalidDuration)?;
+    let machine_key = ctx.accounts.machine.key();
     let machine = &ctx.accounts.machine;
     require_gte!(
         machine.max_duration,
@@ -86,30 +180,69 @@ pub fn renew_order(ctx: Context<RenewOrder>, duration: u32) -> Result<()> {
         DistriAIError::DurationTooMuch
     );
 
-    let total = order.price.saturating_mul(duration.into());
-
-    order.duration = new_duration;
-    order.total = order.total.saturating_add(total);
-
-    emit!(OrderEvent {
-        order_id: order.order_id,
-        buyer: order.buyer,
-        seller: order.seller,
-        machine_id: order.machine_id,
-    });
+    // Capacity booked by reservations starting during the extension can't be taken
+    let billing_period = ctx.accounts.config.billing_period;
+    let old_end_time = order.end_time(billing_period)?;
+    let new_end_time = old_end_time
+        .checked_add(Order::duration_seconds(duration, billing_period)?)
+        .ok_or(DistriAIError::MathOverflow)?;
+    let calendar = &mut ctx.accounts.calendar;
+    calendar.machine = machine_key;
+    // The units of this order are allocated already, only other orders take capacity from it
+    let free = machine
+        .capacity
+        .saturating_sub(machine.allocated)
+        .saturating_add(order.units);
+    require_gte!(
+        u64::from(free),
+        calendar.booked_units(old_end_time, new_end_time) + u64::from(order.units),
+        DistriAIError::ReservationConflict
+    );
 
-    // Transfer token from buyer to vault
-    let total = machine.price.saturating_mul(duration.into());
+    // Transfer token from buyer to escrow
+    let total = order.renewal_amount(duration, billing_period)?;
+    let escrow_before = ctx.accounts.escrow.amount;
     let cpi_context = CpiContext::new(
         ctx.accounts.token_program.to_account_info(),
         TransferChecked {
             from: ctx.accounts.buyer_ata.to_account_info(),
             mint: ctx.accounts.mint.to_account_info(),
-            to: ctx.accounts.vault.to_account_info(),
+            to: ctx.accounts.escrow.to_account_info(),
             authority: ctx.accounts.buyer.to_account_info(),
         },
     );
     transfer_checked(cpi_context, total, ctx.accounts.mint.decimals)?;
+
+    // Record the amount actually received, transfer fees are paid by the buyer
+    ctx.accounts.escrow.reload()?;
+    let total = ctx
+        .accounts
+        .escrow
+        .amount
+        .checked_sub(escrow_before)
+        .ok_or(DistriAIError::MathOverflow)?;
+
+    let order = &mut ctx.accounts.order;
+    order.duration = new_duration;
+    order.total = order
+        .total
+        .checked_add(total)
+        .ok_or(DistriAIError::MathOverflow)?;
+
+    emit!(OrderRenewedEvent {
+        order_id: order.order_id,
+        buyer: order.buyer,
+        seller: order.seller,
+        machine_id: order.machine_id,
+        old_status: order.status.clone(),
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        added_duration: duration,
+        paid_amount: total,
+        total: order.total,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
     Ok(())
 }
 
@@ -121,20 +254,27 @@ pub fn start_order(ctx: Context<StartOrder>) -> Result<()> {
         DistriAIError::IncorrectStatus
     );
 
+    let old_status = order.status.clone();
     order.status = OrderStatus::Training;
     order.start_time = Clock::get()?.unix_timestamp;
 
-    emit!(OrderEvent {
+    emit!(OrderStartedEvent {
         order_id: order.order_id,
         buyer: order.buyer,
         seller: order.seller,
         machine_id: order.machine_id,
+        old_status,
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        timestamp: order.start_time,
     });
     Ok(())
 }
 
 // refund_order is a Solana program function to process a refund for an order.
 pub fn refund_order(ctx: Context<RefundOrder>) -> Result<()> {
+    let order_key = ctx.accounts.order.key();
     let order = &mut ctx.accounts.order;
     require!(
         order.status == OrderStatus::Preparing || order.status == OrderStatus::Training,
@@ -142,98 +282,85 @@ pub fn refund_order(ctx: Context<RefundOrder>) -> Result<()> {
     );
 
     let now_ts = Clock::get()?.unix_timestamp;
-    if order.status == OrderStatus::Preparing {
+    let old_status = order.status.clone();
+    let used_total = if order.status == OrderStatus::Preparing {
         let order_cancelable_time = order
             .order_time
-            .saturating_add(300);
+            .checked_add(ctx.accounts.config.refund_window)
+            .ok_or(DistriAIError::MathOverflow)?;
         require_gte!(now_ts, order_cancelable_time, DistriAIError::IncorrectStatus);
 
         order.status = OrderStatus::Refunded;
 
         let machine = &mut ctx.accounts.machine;
-        machine.status = MachineStatus::ForRent;
+        machine.release(order.units)?;
+        machine.close_order();
         machine.failed_count = machine.failed_count.saturating_add(1);
-
-        // Transfer token from vault to buyer
-        let mint_key = ctx.accounts.mint.key();
-        let signer: &[&[&[u8]]] = &[&[b"vault", mint_key.as_ref(), &[ctx.bumps.vault]]];
-        let cpi_context = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            TransferChecked {
-                from: ctx.accounts.vault.to_account_info(),
-                mint: ctx.accounts.mint.to_account_info(),
-                to: ctx.accounts.buyer_ata.to_account_info(),
-                authority: ctx.accounts.vault.to_account_info(),
-            },
-            signer,
-        );
-        transfer_checked(cpi_context, order.total, ctx.accounts.mint.decimals)?;
+        0
     } else {
-        let used_duration: u32 = now_ts
-            .saturating_sub(order.start_time)
-            .saturating_div(3600)
-            .saturating_add(1)
-            .try_into()
-            .unwrap();
-
-        require_gt!(
-            order.duration,
-            used_duration,
-            DistriAIError::IncorrectStatus
-        );
+        let billing_period = ctx.accounts.config.billing_period;
+        let used_seconds = now_ts
+            .checked_sub(order.start_time)
+            .ok_or(DistriAIError::MathOverflow)?;
+        let order_seconds = Order::duration_seconds(order.duration, billing_period)?;
+        require_gt!(order_seconds, used_seconds, DistriAIError::IncorrectStatus);
 
         order.status = OrderStatus::Refunded;
         order.refund_time = now_ts;
 
         let machine = &mut ctx.accounts.machine;
-        machine.status = MachineStatus::ForRent;
+        machine.release(order.units)?;
+        machine.close_order();
         machine.completed_count = machine.completed_count.saturating_add(1);
 
-        // Transfer token from vault to seller
-        let used_total = order.price.saturating_mul(used_duration.into());
-        let mint_key = ctx.accounts.mint.key();
-        let signer: &[&[&[u8]]] = &[&[b"vault", mint_key.as_ref(), &[ctx.bumps.vault]]];
-        let cpi_context_seller = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            TransferChecked {
-                from: ctx.accounts.vault.to_account_info(),
-                mint: ctx.accounts.mint.to_account_info(),
-                to: ctx.accounts.seller_ata.to_account_info(),
-                authority: ctx.accounts.vault.to_account_info(),
-            },
-            signer,
-        );
-        transfer_checked(cpi_context_seller, used_total, ctx.accounts.mint.decimals)?;
-
-        // Transfer token from vault to buyer
-        let cpi_context_buyer = CpiContext::new_with_signer(
-            ctx.accounts.token_program.to_account_info(),
-            TransferChecked {
-                from: ctx.accounts.vault.to_account_info(),
-                mint: ctx.accounts.mint.to_account_info(),
-                to: ctx.accounts.buyer_ata.to_account_info(),
-                authority: ctx.accounts.vault.to_account_info(),
-            },
-            signer,
-        );
-        transfer_checked(
-            cpi_context_buyer,
-            order.total.saturating_sub(used_total),
-            ctx.accounts.mint.decimals,
-        )?;
-    }
+        Order::billed_amount(
+            order.price,
+            order.units,
+            order.billing_granularity,
+            billing_period,
+            used_seconds,
+        )?
+        .min(order.total)
+    };
+
+    // Pay the used time to the seller and refund the rest to the buyer
+    let signer: &[&[&[u8]]] = &[&[b"escrow", order_key.as_ref(), &[ctx.bumps.escrow]]];
+    let (seller_amount, buyer_amount, fee) = pay_out_escrow(
+        &ctx.accounts.config,
+        PayoutAccounts {
+            token_program: &ctx.accounts.token_program,
+            escrow: &ctx.accounts.escrow,
+            mint: &ctx.accounts.mint,
+            seller_ata: ctx.accounts.seller_ata.to_account_info(),
+            treasury_ata: ctx.accounts.treasury_ata.to_account_info(),
+            buyer_ata: Some(ctx.accounts.buyer_ata.to_account_info()),
+            buyer: ctx.accounts.buyer.to_account_info(),
+        },
+        signer,
+        order.total,
+        used_total,
+    )?;
 
-    emit!(OrderEvent {
+    emit!(OrderRefundedEvent {
         order_id: order.order_id,
         buyer: order.buyer,
         seller: order.seller,
         machine_id: order.machine_id,
+        old_status,
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        seller_amount,
+        buyer_amount,
+        fee,
+        timestamp: now_ts,
     });
     Ok(())
 }
 
 // Define a public function `order_completed` to handle the completion of an order.
-pub fn order_completed(ctx: Context<OrderCompleted>, metadata: String, score: u8) -> Result<()> {
+// The payment stays in escrow until the challenge period ends, see `release_payment`.
+pub fn order_completed(ctx: Context<OrderCompleted>, metadata: String) -> Result<()> {
     require_gte!(
         Order::METADATA_MAX_LENGTH,
         metadata.len(),
@@ -246,42 +373,80 @@ pub fn order_completed(ctx: Context<OrderCompleted>, metadata: String, score: u8
         DistriAIError::IncorrectStatus
     );
     let now_ts = Clock::get()?.unix_timestamp;
-    let order_endtime = order
-        .start_time
-        .saturating_add(order.duration.saturating_mul(3600).into());
+    let order_endtime = order.end_time(ctx.accounts.config.billing_period)?;
     require_gte!(now_ts, order_endtime, DistriAIError::IncorrectStatus);
+    let old_status = order.status.clone();
     order.metadata = metadata;
-    order.status = OrderStatus::Completed;
+    order.status = OrderStatus::Delivered;
+    order.complete_time = now_ts;
 
-    let machine = &mut ctx.accounts.machine;
+    // The capacity is free for new orders, the completion counts once the payment is released
+    ctx.accounts.machine.release(order.units)?;
+
+    emit!(OrderCompletedEvent {
+        order_id: order.order_id,
+        buyer: order.buyer,
+        seller: order.seller,
+        machine_id: order.machine_id,
+        old_status,
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        challenge_end_time: order.challenge_end_time(ctx.accounts.config.challenge_period)?,
+        timestamp: now_ts,
+    });
+    Ok(())
+}
+
+/// Pays the seller of a delivered order once its challenge period has passed without a dispute.
+/// Anyone can call it.
+pub fn release_payment(ctx: Context<ReleasePayment>) -> Result<()> {
+    let order_key = ctx.accounts.order.key();
+    let order = &mut ctx.accounts.order;
     require!(
-        machine.status == MachineStatus::Renting,
+        order.status == OrderStatus::Delivered,
         DistriAIError::IncorrectStatus
     );
-    machine.status = MachineStatus::ForRent;
+    let now_ts = Clock::get()?.unix_timestamp;
+    let challenge_end_time = order.challenge_end_time(ctx.accounts.config.challenge_period)?;
+    require_gte!(now_ts, challenge_end_time, DistriAIError::IncorrectStatus);
+    let old_status = order.status.clone();
+    order.status = OrderStatus::Completed;
+
+    let machine = &mut ctx.accounts.machine;
+    machine.close_order();
     machine.completed_count = machine.completed_count.saturating_add(1);
-    machine.score = score;
 
-    // Transfer token from vault to seller
-    let mint_key = ctx.accounts.mint.key();
-    let signer: &[&[&[u8]]] = &[&[b"vault", mint_key.as_ref(), &[ctx.bumps.vault]]];
-    let cpi_context = CpiContext::new_with_signer(
-        ctx.accounts.token_program.to_account_info(),
-        TransferChecked {
-            from: ctx.accounts.vault.to_account_info(),
-            mint: ctx.accounts.mint.to_account_info(),
-            to: ctx.accounts.seller_ata.to_account_info(),
-            authority: ctx.accounts.vault.to_account_info(),
+    // Transfer token from escrow to seller, less the protocol fee sent to the treasury
+    let signer: &[&[&[u8]]] = &[&[b"escrow", order_key.as_ref(), &[ctx.bumps.escrow]]];
+    let (seller_amount, _, fee) = pay_out_escrow(
+        &ctx.accounts.config,
+        PayoutAccounts {
+            token_program: &ctx.accounts.token_program,
+            escrow: &ctx.accounts.escrow,
+            mint: &ctx.accounts.mint,
+            seller_ata: ctx.accounts.seller_ata.to_account_info(),
+            treasury_ata: ctx.accounts.treasury_ata.to_account_info(),
+            buyer_ata: None,
+            buyer: ctx.accounts.buyer.to_account_info(),
         },
         signer,
-    );
-    transfer_checked(cpi_context, order.total, ctx.accounts.mint.decimals)?;
+        order.total,
+        order.total,
+    )?;
 
-    emit!(OrderEvent {
+    emit!(OrderPaymentReleasedEvent {
         order_id: order.order_id,
         buyer: order.buyer,
         seller: order.seller,
         machine_id: order.machine_id,
+        old_status,
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        seller_amount,
+        fee,
+        timestamp: now_ts,
     });
     Ok(())
 }
@@ -294,42 +459,228 @@ pub fn order_failed(ctx: Context<OrderFailed>, metadata: String) -> Result<()> {
         DistriAIError::StringTooLong
     );
 
+    let order_key = ctx.accounts.order.key();
+    let now_ts = Clock::get()?.unix_timestamp;
     let order = &mut ctx.accounts.order;
     require!(
         order.status == OrderStatus::Preparing || order.status == OrderStatus::Training,
         DistriAIError::IncorrectStatus
     );
+    let old_status = order.status.clone();
     order.metadata = metadata;
     order.status = OrderStatus::Failed;
 
     let machine = &mut ctx.accounts.machine;
+    machine.release(order.units)?;
+    machine.close_order();
+    machine.failed_count = machine.failed_count.saturating_add(1);
+    slash_stake(
+        &ctx.accounts.config,
+        machine,
+        order.order_id,
+        ctx.bumps.stake_vault,
+        SlashAccounts {
+            token_program: &ctx.accounts.stake_token_program,
+            stake_vault: &ctx.accounts.stake_vault,
+            stake_mint: &ctx.accounts.stake_mint,
+            buyer_stake_ata: ctx.accounts.buyer_stake_ata.to_account_info(),
+            treasury_stake_ata: ctx.accounts.treasury_stake_ata.to_account_info(),
+        },
+        now_ts,
+    )?;
+
+    // Transfer token from escrow to buyer
+    let signer: &[&[&[u8]]] = &[&[b"escrow", order_key.as_ref(), &[ctx.bumps.escrow]]];
+    transfer_from_escrow(
+        &ctx.accounts.token_program,
+        &ctx.accounts.escrow,
+        &ctx.accounts.mint,
+        ctx.accounts.buyer_ata.to_account_info(),
+        signer,
+        order.total,
+    )?;
+    close_escrow(
+        &ctx.accounts.token_program,
+        &ctx.accounts.escrow,
+        &ctx.accounts.mint,
+        ctx.accounts.buyer.to_account_info(),
+        signer,
+    )?;
+
+    emit!(OrderFailedEvent {
+        order_id: order.order_id,
+        buyer: order.buyer,
+        seller: order.seller,
+        machine_id: order.machine_id,
+        old_status,
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        buyer_amount: order.total,
+        timestamp: now_ts,
+    });
+    Ok(())
+}
+
+/// Expires a preparing order that the seller never started, refunding the buyer. Anyone can call it.
+pub fn expire_order(ctx: Context<ExpireOrder>) -> Result<()> {
+    let order_key = ctx.accounts.order.key();
+    let order = &mut ctx.accounts.order;
     require!(
-        machine.status == MachineStatus::Renting,
+        order.status == OrderStatus::Preparing,
         DistriAIError::IncorrectStatus
     );
-    machine.status = MachineStatus::ForRent;
+    let now_ts = Clock::get()?.unix_timestamp;
+    let order_expire_time = order
+        .order_time
+        .checked_add(ctx.accounts.config.start_timeout)
+        .ok_or(DistriAIError::MathOverflow)?;
+    require_gte!(now_ts, order_expire_time, DistriAIError::IncorrectStatus);
+    let old_status = order.status.clone();
+    order.status = OrderStatus::Refunded;
+    order.refund_time = now_ts;
+
+    let machine = &mut ctx.accounts.machine;
+    machine.release(order.units)?;
+    machine.close_order();
     machine.failed_count = machine.failed_count.saturating_add(1);
+    slash_stake(
+        &ctx.accounts.config,
+        machine,
+        order.order_id,
+        ctx.bumps.stake_vault,
+        SlashAccounts {
+            token_program: &ctx.accounts.stake_token_program,
+            stake_vault: &ctx.accounts.stake_vault,
+            stake_mint: &ctx.accounts.stake_mint,
+            buyer_stake_ata: ctx.accounts.buyer_stake_ata.to_account_info(),
+            treasury_stake_ata: ctx.accounts.treasury_stake_ata.to_account_info(),
+        },
+        now_ts,
+    )?;
+
+    // Transfer token from escrow to buyer
+    let signer: &[&[&[u8]]] = &[&[b"escrow", order_key.as_ref(), &[ctx.bumps.escrow]]];
+    transfer_from_escrow(
+        &ctx.accounts.token_program,
+        &ctx.accounts.escrow,
+        &ctx.accounts.mint,
+        ctx.accounts.buyer_ata.to_account_info(),
+        signer,
+        order.total,
+    )?;
+    close_escrow(
+        &ctx.accounts.token_program,
+        &ctx.accounts.escrow,
+        &ctx.accounts.mint,
+        ctx.accounts.buyer.to_account_info(),
+        signer,
+    )?;
 
-    // Transfer token from vault to seller
-    let mint_key = ctx.accounts.mint.key();
-    let signer: &[&[&[u8]]] = &[&[b"vault", mint_key.as_ref(), &[ctx.bumps.vault]]];
-    let cpi_context = CpiContext::new_with_signer(
-        ctx.accounts.token_program.to_account_info(),
-        TransferChecked {
-            from: ctx.accounts.vault.to_account_info(),
-            mint: ctx.accounts.mint.to_account_info(),
-            to: ctx.accounts.buyer_ata.to_account_info(),
-            authority: ctx.accounts.vault.to_account_info(),
+    emit!(OrderExpiredEvent {
+        order_id: order.order_id,
+        buyer: order.buyer,
+        seller: order.seller,
+        machine_id: order.machine_id,
+        old_status,
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        buyer_amount: order.total,
+        timestamp: now_ts,
+    });
+    Ok(())
+}
+
+/// Settles a training order the seller never completed, once the grace period after its end
+/// has passed. Anyone can call it.
+///
+/// The order total is split by the configured seller share, the rest is refunded to the buyer.
+/// The seller did not complete the order, so it does not count towards the machine's completions.
+pub fn settle_order(ctx: Context<SettleOrder>) -> Result<()> {
+    let order_key = ctx.accounts.order.key();
+    let order = &mut ctx.accounts.order;
+    require!(
+        order.status == OrderStatus::Training,
+        DistriAIError::IncorrectStatus
+    );
+    let now_ts = Clock::get()?.unix_timestamp;
+    let order_settle_time = order
+        .end_time(ctx.accounts.config.billing_period)?
+        .checked_add(ctx.accounts.config.settle_grace_period)
+        .ok_or(DistriAIError::MathOverflow)?;
+    require_gte!(now_ts, order_settle_time, DistriAIError::IncorrectStatus);
+    let old_status = order.status.clone();
+    order.status = OrderStatus::Completed;
+
+    let machine = &mut ctx.accounts.machine;
+    machine.release(order.units)?;
+    machine.close_order();
+
+    // Pay the seller share to the seller and refund the rest to the buyer
+    let used_total = Config::bps_of(order.total, ctx.accounts.config.settle_seller_bps);
+    let signer: &[&[&[u8]]] = &[&[b"escrow", order_key.as_ref(), &[ctx.bumps.escrow]]];
+    let (seller_amount, buyer_amount, fee) = pay_out_escrow(
+        &ctx.accounts.config,
+        PayoutAccounts {
+            token_program: &ctx.accounts.token_program,
+            escrow: &ctx.accounts.escrow,
+            mint: &ctx.accounts.mint,
+            seller_ata: ctx.accounts.seller_ata.to_account_info(),
+            treasury_ata: ctx.accounts.treasury_ata.to_account_info(),
+            buyer_ata: Some(ctx.accounts.buyer_ata.to_account_info()),
+            buyer: ctx.accounts.buyer.to_account_info(),
         },
         signer,
+        order.total,
+        used_total,
+    )?;
+
+    emit!(OrderSettledEvent {
+        order_id: order.order_id,
+        buyer: order.buyer,
+        seller: order.seller,
+        machine_id: order.machine_id,
+        old_status,
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        seller_amount,
+        buyer_amount,
+        fee,
+        timestamp: now_ts,
+    });
+    Ok(())
+}
+
+/// Rates a completed or refunded order, only the buyer can rate and only once.
+pub fn rate_order(ctx: Context<RateOrder>, rating: u8) -> Result<()> {
+    require!(
+        (Order::MIN_RATING..=Order::MAX_RATING).contains(&rating),
+        DistriAIError::InvalidRating
+    );
+
+    let order = &mut ctx.accounts.order;
+    require!(
+        order.status == OrderStatus::Completed || order.status == OrderStatus::Refunded,
+        DistriAIError::IncorrectStatus
     );
-    transfer_checked(cpi_context, order.total, ctx.accounts.mint.decimals)?;
+    require_eq!(order.rating, 0, DistriAIError::RepeatRating);
+    order.rating = rating;
 
-    emit!(OrderEvent {
+    let machine = &mut ctx.accounts.machine;
+    machine.rating_sum = machine.rating_sum.saturating_add(rating.into());
+    machine.rating_count = machine.rating_count.saturating_add(1);
+
+    emit!(OrderRatedEvent {
         order_id: order.order_id,
         buyer: order.buyer,
         seller: order.seller,
         machine_id: order.machine_id,
+        rating,
+        rating_sum: machine.rating_sum,
+        rating_count: machine.rating_count,
+        timestamp: Clock::get()?.unix_timestamp,
     });
     Ok(())
 }
@@ -338,28 +689,191 @@ pub fn order_failed(ctx: Context<OrderFailed>, metadata: String) -> Result<()> {
 pub fn remove_order(ctx: Context<RemoveOrder>) -> Result<()> {
     let order = &mut ctx.accounts.order;
     require!(
-        order.status != OrderStatus::Preparing || order.status != OrderStatus::Training,
+        !matches!(
+            order.status,
+            OrderStatus::Preparing
+                | OrderStatus::Training
+                | OrderStatus::Disputed
+                | OrderStatus::Reserved
+                | OrderStatus::Delivered
+        ),
         DistriAIError::IncorrectStatus
     );
 
-    emit!(OrderEvent {
+    emit!(OrderRemovedEvent {
         order_id: order.order_id,
         buyer: order.buyer,
         seller: order.seller,
         machine_id: order.machine_id,
+        status: order.status.clone(),
+        timestamp: Clock::get()?.unix_timestamp,
     });
     Ok(())
 }
 
+/// The token accounts an order escrow is paid out to.
+pub(crate) struct PayoutAccounts<'a, 'info> {
+    pub token_program: &'a Interface<'info, TokenInterface>,
+    pub escrow: &'a InterfaceAccount<'info, TokenAccount>,
+    pub mint: &'a InterfaceAccount<'info, Mint>,
+    pub seller_ata: AccountInfo<'info>,
+    pub treasury_ata: AccountInfo<'info>,
+    /// Only needed if part of the escrow is refunded.
+    pub buyer_ata: Option<AccountInfo<'info>>,
+    /// Receives the rent of the closed escrow.
+    pub buyer: AccountInfo<'info>,
+}
+
+/// Pays `used_total` of an escrow holding `total` to the seller, less the protocol fee sent to
+/// the treasury, refunds the rest to the buyer and closes the escrow.
+///
+/// Returns the seller amount, the buyer amount and the fee.
+pub(crate) fn pay_out_escrow<'info>(
+    config: &Config,
+    accounts: PayoutAccounts<'_, 'info>,
+    signer: &[&[&[u8]]],
+    total: u64,
+    used_total: u64,
+) -> Result<(u64, u64, u64)> {
+    let fee = Config::bps_of(used_total, config.fee_bps);
+    let seller_amount = used_total - fee;
+    let buyer_amount = total
+        .checked_sub(used_total)
+        .ok_or(DistriAIError::MathOverflow)?;
+    if seller_amount > 0 {
+        transfer_from_escrow(
+            accounts.token_program,
+            accounts.escrow,
+            accounts.mint,
+            accounts.seller_ata,
+            signer,
+            seller_amount,
+        )?;
+    }
+    if fee > 0 {
+        transfer_from_escrow(
+            accounts.token_program,
+            accounts.escrow,
+            accounts.mint,
+            accounts.treasury_ata,
+            signer,
+            fee,
+        )?;
+    }
+    if buyer_amount > 0 {
+        transfer_from_escrow(
+            accounts.token_program,
+            accounts.escrow,
+            accounts.mint,
+            accounts.buyer_ata.ok_or(DistriAIError::IncorrectStatus)?,
+            signer,
+            buyer_amount,
+        )?;
+    }
+    close_escrow(
+        accounts.token_program,
+        accounts.escrow,
+        accounts.mint,
+        accounts.buyer,
+        signer,
+    )?;
+    Ok((seller_amount, buyer_amount, fee))
+}
+
+/// Transfers `amount` from the order escrow, signed by the escrow PDA.
+pub(crate) fn transfer_from_escrow<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    escrow: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: AccountInfo<'info>,
+    signer: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    let cpi_context = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        TransferChecked {
+            from: escrow.to_account_info(),
+            mint: mint.to_account_info(),
+            to,
+            authority: escrow.to_account_info(),
+        },
+        signer,
+    );
+    transfer_checked(cpi_context, amount, mint.decimals)
+}
+
+/// Closes the emptied order escrow and returns its rent to `destination`.
+pub(crate) fn close_escrow<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    escrow: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    destination: AccountInfo<'info>,
+    signer: &[&[&[u8]]],
+) -> Result<()> {
+    // Transfer fees withheld in the escrow must be harvested to the mint before closing
+    if token_program.key() == spl_token_2022::ID {
+        let withheld_amount = {
+            let escrow_info = escrow.to_account_info();
+            let escrow_data = escrow_info.try_borrow_data()?;
+            let escrow_state =
+                StateWithExtensions::<spl_token_2022::state::Account>::unpack(&escrow_data)?;
+            escrow_state
+                .get_extension::<TransferFeeAmount>()
+                .map_or(0, |fee_amount| u64::from(fee_amount.withheld_amount))
+        };
+        if withheld_amount > 0 {
+            let harvest = harvest_withheld_tokens_to_mint(
+                &token_program.key(),
+                &mint.key(),
+                &[&escrow.key()],
+            )?;
+            invoke(
+                &harvest,
+                &[
+                    mint.to_account_info(),
+                    escrow.to_account_info(),
+                    token_program.to_account_info(),
+                ],
+            )?;
+        }
+    }
+
+    let cpi_context = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: escrow.to_account_info(),
+            destination,
+            authority: escrow.to_account_info(),
+        },
+        signer,
+    );
+    close_account(cpi_context)
+}
+
 // The `derive(Accounts)` macro generates a struct that represents a group of Solana accounts.
 // The `instruction` attribute specifies a parameter that will be passed to the program.
 #[derive(Accounts)]
 #[instruction(order_id: [u8; 16])]
 pub struct PlaceOrder<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
     // Mutable reference to the Machine account
     #[account(mut)]
     pub machine: Box<Account<'info, Machine>>,
 
+    #[account(
+        init_if_needed,
+        seeds = [b"calendar", machine.key().as_ref()],
+        bump,
+        payer = buyer,
+        space = 8 + Calendar::INIT_SPACE
+    )]
+    pub calendar: Box<Account<'info, Calendar>>,
+
     #[account(
         init,
         seeds = [b"order", buyer.key().as_ref(), order_id.as_ref()],
@@ -375,37 +889,61 @@ pub struct PlaceOrder<'info> {
     #[account(
         mut,
         associated_token::mint = mint,
-        associated_token::authority = buyer
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
     )]
-    pub buyer_ata: Account<'info, TokenAccount>,
+    pub buyer_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
-        init_if_needed,
-        seeds = [b"vault", mint.key().as_ref()],
+        init,
+        seeds = [b"escrow", order.key().as_ref()],
         bump,
         payer = buyer,
         token::mint = mint,
-        token::authority = vault
+        token::authority = escrow,
+        token::token_program = token_program
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
 
+    // The payment mint must still be allowed by the admin
     #[account(
-        address = dist_token::ID
+        seeds = [b"payment-mint", mint.key().as_ref()],
+        bump
     )]
-    pub mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
+    pub payment_mint: Box<Account<'info, PaymentMint>>,
+
+    #[account(
+        address = machine.mint
+    )]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
 pub struct RenewOrder<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
     #[account(
         mut,
         constraint = machine.uuid == order.machine_id && machine.owner == order.seller
     )]
     pub machine: Box<Account<'info, Machine>>,
 
+    #[account(
+        init_if_needed,
+        seeds = [b"calendar", machine.key().as_ref()],
+        bump,
+        payer = buyer,
+        space = 8 + Calendar::INIT_SPACE
+    )]
+    pub calendar: Box<Account<'info, Calendar>>,
+
     #[account(
         mut,
         has_one = buyer
@@ -418,23 +956,25 @@ pub struct RenewOrder<'info> {
     #[account(
         mut,
         associated_token::mint = mint,
-        associated_token::authority = buyer
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
     )]
-    pub buyer_ata: Account<'info, TokenAccount>,
+    pub buyer_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        seeds = [b"vault", mint.key().as_ref()],
+        seeds = [b"escrow", order.key().as_ref()],
         bump
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
-        address = dist_token::ID
+        address = order.mint
     )]
-    pub mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -451,6 +991,12 @@ pub struct StartOrder<'info> {
 
 #[derive(Accounts)]
 pub struct RefundOrder<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
     #[account(
         mut,
         constraint = machine.uuid == order.machine_id && machine.owner == order.seller
@@ -469,35 +1015,59 @@ pub struct RefundOrder<'info> {
     #[account(
         mut,
         associated_token::mint = mint,
-        associated_token::authority = buyer
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
     )]
-    pub buyer_ata: Account<'info, TokenAccount>,
+    pub buyer_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
         associated_token::mint = mint,
-        associated_token::authority = order.seller
+        associated_token::authority = order.seller,
+        associated_token::token_program = token_program
     )]
-    pub seller_ata: Account<'info, TokenAccount>,
+    pub seller_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Owner of the treasury token account.
+    #[account(
+        address = config.treasury
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = mint,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program
+    )]
+    pub treasury_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        seeds = [b"vault", mint.key().as_ref()],
+        seeds = [b"escrow", order.key().as_ref()],
         bump
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
-        address = dist_token::ID
+        mut,
+        address = order.mint
     )]
-    pub mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
 pub struct OrderCompleted<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
     #[account(
         mut,
         constraint = machine.uuid == order.machine_id && machine.owner == order.seller
@@ -510,35 +1080,90 @@ pub struct OrderCompleted<'info> {
     )]
     pub order: Box<Account<'info, Order>>,
 
-    #[account(mut)]
     pub seller: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReleasePayment<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        constraint = machine.uuid == order.machine_id && machine.owner == order.seller
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    #[account(mut)]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: Receives the rent of the closed escrow.
+    #[account(
+        mut,
+        address = order.buyer
+    )]
+    pub buyer: UncheckedAccount<'info>,
+
+    /// CHECK: Owner of the seller token account.
+    #[account(
+        address = order.seller
+    )]
+    pub seller: UncheckedAccount<'info>,
 
     #[account(
         init_if_needed,
-        payer = seller,
+        payer = signer,
         associated_token::mint = mint,
-        associated_token::authority = seller
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
     )]
-    pub seller_ata: Account<'info, TokenAccount>,
+    pub seller_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Owner of the treasury token account.
+    #[account(
+        address = config.treasury
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program
+    )]
+    pub treasury_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        seeds = [b"vault", mint.key().as_ref()],
+        seeds = [b"escrow", order.key().as_ref()],
         bump
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
-        address = dist_token::ID
+        mut,
+        address = order.mint
     )]
-    pub mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
 pub struct OrderFailed<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
     #[account(
         mut,
         constraint = machine.uuid == order.machine_id && machine.owner == order.seller
@@ -554,26 +1179,268 @@ pub struct OrderFailed<'info> {
     #[account(mut)]
     pub seller: Signer<'info>,
 
+    /// CHECK: Receives the rent of the closed escrow.
+    #[account(
+        mut,
+        address = order.buyer
+    )]
+    pub buyer: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = order.buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", order.key().as_ref()],
+        bump
+    )]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        address = order.mint
+    )]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        seeds = [b"stake", machine.key().as_ref()],
+        bump,
+        payer = seller,
+        token::mint = stake_mint,
+        token::authority = stake_vault,
+        token::token_program = stake_token_program
+    )]
+    pub stake_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = stake_mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = stake_token_program
+    )]
+    pub buyer_stake_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: The owner of the treasury token accounts.
+    #[account(
+        address = config.treasury
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = seller,
+        associated_token::mint = stake_mint,
+        associated_token::authority = treasury,
+        associated_token::token_program = stake_token_program
+    )]
+    pub treasury_stake_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        address = config.mint
+    )]
+    pub stake_mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub stake_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireOrder<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        constraint = machine.uuid == order.machine_id && machine.owner == order.seller
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    #[account(mut)]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: Receives the rent of the closed escrow.
+    #[account(
+        mut,
+        address = order.buyer
+    )]
+    pub buyer: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = order.buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", order.key().as_ref()],
+        bump
+    )]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        address = order.mint
+    )]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        seeds = [b"stake", machine.key().as_ref()],
+        bump,
+        payer = signer,
+        token::mint = stake_mint,
+        token::authority = stake_vault,
+        token::token_program = stake_token_program
+    )]
+    pub stake_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = stake_mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = stake_token_program
+    )]
+    pub buyer_stake_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: The owner of the treasury token accounts.
+    #[account(
+        address = config.treasury
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = stake_mint,
+        associated_token::authority = treasury,
+        associated_token::token_program = stake_token_program
+    )]
+    pub treasury_stake_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        address = config.mint
+    )]
+    pub stake_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub stake_token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SettleOrder<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        constraint = machine.uuid == order.machine_id && machine.owner == order.seller
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    #[account(mut)]
+    pub order: Box<Account<'info, Order>>,
+
+    /// CHECK: Receives the rent of the closed escrow.
     #[account(
         mut,
+        address = order.buyer
+    )]
+    pub buyer: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
         associated_token::mint = mint,
-        associated_token::authority = order.buyer
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
     )]
-    pub buyer_ata: Account<'info, TokenAccount>,
+    pub buyer_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Owner of the seller token account.
+    #[account(
+        address = order.seller
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Owner of the treasury token account.
+    #[account(
+        address = config.treasury
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = mint,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program
+    )]
+    pub treasury_ata: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        seeds = [b"vault", mint.key().as_ref()],
+        seeds = [b"escrow", order.key().as_ref()],
         bump
     )]
-    pub vault: Account<'info, TokenAccount>,
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
-        address = dist_token::ID
+        mut,
+        address = order.mint
     )]
-    pub mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mut)]
+    pub signer: Signer<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RateOrder<'info> {
+    #[account(
+        mut,
+        constraint = machine.uuid == order.machine_id && machine.owner == order.seller
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    #[account(
+        mut,
+        has_one = buyer
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    pub buyer: Signer<'info>,
 }
 
 #[derive(Accounts)]
@@ -590,9 +1457,164 @@ pub struct RemoveOrder<'info> {
 }
 
 #[event]
-pub struct OrderEvent {
+pub struct OrderPlacedEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub mint: Pubkey,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    pub units: u32,
+    /// The amount escrowed from the buyer.
+    pub paid_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderRenewedEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    /// The hours added to the order.
+    pub added_duration: u32,
+    /// The amount escrowed from the buyer for the added hours.
+    pub paid_amount: u64,
+    /// The total amount escrowed for the order.
+    pub total: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderStartedEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderRefundedEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    pub seller_amount: u64,
+    pub buyer_amount: u64,
+    /// The protocol fee sent to the treasury.
+    pub fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderCompletedEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    /// The time the payment can be released unless the buyer disputes the order.
+    pub challenge_end_time: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderPaymentReleasedEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    pub seller_amount: u64,
+    /// The protocol fee sent to the treasury.
+    pub fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderFailedEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    pub buyer_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderExpiredEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    pub buyer_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderSettledEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    pub seller_amount: u64,
+    pub buyer_amount: u64,
+    /// The protocol fee sent to the treasury.
+    pub fee: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderRatedEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub rating: u8,
+    pub rating_sum: u64,
+    pub rating_count: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderRemovedEvent {
     pub order_id: [u8; 16],
     pub buyer: Pubkey,
     pub seller: Pubkey,
     pub machine_id: [u8; 16],
+    pub status: OrderStatus,
+    pub timestamp: i64,
 }
@@ -0,0 +1,491 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+use crate::errors::DistriAIError;
+use crate::instructions::order::{pay_out_escrow, PayoutAccounts};
+use crate::state::calendar::*;
+use crate::state::config::*;
+use crate::state::machine::*;
+use crate::state::order::*;
+use crate::state::payment_mint::*;
+use crate::state::Versioned;
+
+/// ReservationParams holds the terms of a reservation.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ReservationParams {
+    /// The scheduled start time.
+    pub start_time: i64,
+    /// The number of hours reserved.
+    pub duration: u32,
+    /// The number of machine capacity units reserved.
+    pub units: u32,
+    /// The highest price per unit the buyer accepts.
+    pub max_price: u64,
+    /// The machine owner the buyer expects.
+    pub expected_machine_owner: Pubkey,
+}
+
+/// Reserves a machine for a future window, escrowing the payment until the reservation is activated.
+///
+/// Only other reservations are checked for overlap, so the machine may still be busy when the
+/// window arrives. The buyer can then cancel the reservation for a full refund.
+pub fn reserve_order(
+    ctx: Context<ReserveOrder>,
+    order_id: [u8; 16],
+    metadata: String,
+    params: ReservationParams,
+) -> Result<()> {
+    require_gte!(
+        Order::METADATA_MAX_LENGTH,
+        metadata.len(),
+        DistriAIError::StringTooLong
+    );
+
+    let now_ts = Clock::get()?.unix_timestamp;
+    let machine_key = ctx.accounts.machine.key();
+    let machine = &mut ctx.accounts.machine;
+    require!(
+        machine.status != MachineStatus::Idle,
+        DistriAIError::IncorrectStatus
+    );
+    // Machines migrated from before staking may be offered without a stake to slash
+    require_gte!(
+        machine.stake,
+        ctx.accounts.config.min_stake,
+        DistriAIError::InsufficientStake
+    );
+    machine.apply_pending_offer(now_ts);
+    require_keys_eq!(
+        machine.owner,
+        params.expected_machine_owner,
+        DistriAIError::UnexpectedMachineOwner
+    );
+    require_gte!(params.max_price, machine.price, DistriAIError::PriceTooHigh);
+    require_gt!(params.duration, 0, DistriAIError::InvalidDuration);
+    require_gte!(
+        machine.max_duration,
+        params.duration,
+        DistriAIError::DurationTooMuch
+    );
+    require_gt!(params.units, 0, DistriAIError::InsufficientCapacity);
+    require_gt!(params.start_time, now_ts, DistriAIError::InvalidStartTime);
+
+    // Book the window in the machine calendar
+    let billing_period = ctx.accounts.config.billing_period;
+    let order_seconds = Order::duration_seconds(params.duration, billing_period)?;
+    let end_time = params
+        .start_time
+        .checked_add(order_seconds)
+        .ok_or(DistriAIError::MathOverflow)?;
+    let calendar = &mut ctx.accounts.calendar;
+    calendar.machine = machine_key;
+    calendar.book(
+        Booking {
+            order: ctx.accounts.order.key(),
+            start_time: params.start_time,
+            end_time,
+            units: params.units,
+        },
+        machine.capacity,
+        now_ts,
+    )?;
+
+    // Transfer token from buyer to escrow
+    let total = Order::billed_amount(
+        machine.price,
+        params.units,
+        machine.billing_granularity,
+        billing_period,
+        order_seconds,
+    )?;
+    let cpi_context = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        TransferChecked {
+            from: ctx.accounts.buyer_ata.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.escrow.to_account_info(),
+            authority: ctx.accounts.buyer.to_account_info(),
+        },
+    );
+    transfer_checked(cpi_context, total, ctx.accounts.mint.decimals)?;
+
+    // Record the amount actually received, transfer fees are paid by the buyer
+    ctx.accounts.escrow.reload()?;
+    let total = ctx.accounts.escrow.amount;
+
+    let order = &mut ctx.accounts.order;
+    order.order_id = order_id;
+    order.buyer = ctx.accounts.buyer.key();
+    order.seller = machine.owner;
+    order.machine_id = machine.uuid;
+    order.price = machine.price;
+    order.duration = params.duration;
+    order.total = total;
+    order.metadata = metadata;
+    order.status = OrderStatus::Reserved;
+    order.order_time = now_ts;
+    order.refund_time = 0;
+    order.version = Order::VERSION;
+    order.mint = machine.mint;
+    order.billing_granularity = machine.billing_granularity;
+    order.units = params.units;
+    order.reserved_time = params.start_time;
+    machine.open_order()?;
+
+    emit!(OrderReservedEvent {
+        order_id: order.order_id,
+        buyer: order.buyer,
+        seller: order.seller,
+        machine_id: order.machine_id,
+        mint: order.mint,
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        units: order.units,
+        reserved_time: order.reserved_time,
+        paid_amount: order.total,
+        timestamp: now_ts,
+    });
+    Ok(())
+}
+
+/// Activates a reservation once its window arrives, anyone can call it.
+pub fn activate_reservation(ctx: Context<ActivateReservation>) -> Result<()> {
+    let order_key = ctx.accounts.order.key();
+    let order = &mut ctx.accounts.order;
+    require!(
+        order.status == OrderStatus::Reserved,
+        DistriAIError::IncorrectStatus
+    );
+    let now_ts = Clock::get()?.unix_timestamp;
+    require_gte!(now_ts, order.reserved_time, DistriAIError::InvalidStartTime);
+
+    let booking = ctx.accounts.calendar.remove(&order_key)?;
+    require_gt!(booking.end_time, now_ts, DistriAIError::InvalidStartTime);
+
+    let machine = &mut ctx.accounts.machine;
+    require!(
+        machine.is_online(now_ts, ctx.accounts.config.heartbeat_timeout),
+        DistriAIError::MachineOffline
+    );
+    machine.allocate(order.units)?;
+    machine.order_pda = order_key;
+
+    // The order now goes through the same flow as a placed order
+    let old_status = order.status.clone();
+    order.status = OrderStatus::Preparing;
+    order.order_time = now_ts;
+
+    emit!(OrderActivatedEvent {
+        order_id: order.order_id,
+        buyer: order.buyer,
+        seller: order.seller,
+        machine_id: order.machine_id,
+        old_status,
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        units: order.units,
+        timestamp: now_ts,
+    });
+    Ok(())
+}
+
+/// Cancels a reservation before it is activated, refunding the buyer.
+///
+/// Cancelling before the reserved window starts pays the cancellation fee to the seller, less the
+/// protocol fee like any seller payout, so blocking a calendar is not free. Once the window started the buyer is refunded in full.
+pub fn cancel_reservation(ctx: Context<CancelReservation>) -> Result<()> {
+    let order_key = ctx.accounts.order.key();
+    let order = &mut ctx.accounts.order;
+    require!(
+        order.status == OrderStatus::Reserved,
+        DistriAIError::IncorrectStatus
+    );
+    let now_ts = Clock::get()?.unix_timestamp;
+    let end_time = order
+        .reserved_time
+        .checked_add(Order::duration_seconds(
+            order.duration,
+            ctx.accounts.config.billing_period,
+        )?)
+        .ok_or(DistriAIError::MathOverflow)?;
+    ctx.accounts.calendar.cancel(&order_key, end_time, now_ts)?;
+    ctx.accounts.machine.close_order();
+
+    let old_status = order.status.clone();
+    order.status = OrderStatus::Refunded;
+    order.refund_time = now_ts;
+
+    let used_total = if now_ts < order.reserved_time {
+        Config::bps_of(order.total, ctx.accounts.config.cancel_fee_bps)
+    } else {
+        0
+    };
+
+    // Pay the cancellation fee to the seller, less the protocol fee sent to the treasury,
+    // and refund the rest to the buyer
+    let signer: &[&[&[u8]]] = &[&[b"escrow", order_key.as_ref(), &[ctx.bumps.escrow]]];
+    let (seller_amount, buyer_amount, fee) = pay_out_escrow(
+        &ctx.accounts.config,
+        PayoutAccounts {
+            token_program: &ctx.accounts.token_program,
+            escrow: &ctx.accounts.escrow,
+            mint: &ctx.accounts.mint,
+            seller_ata: ctx.accounts.seller_ata.to_account_info(),
+            treasury_ata: ctx.accounts.treasury_ata.to_account_info(),
+            buyer_ata: Some(ctx.accounts.buyer_ata.to_account_info()),
+            buyer: ctx.accounts.buyer.to_account_info(),
+        },
+        signer,
+        order.total,
+        used_total,
+    )?;
+
+    emit!(ReservationCancelledEvent {
+        order_id: order.order_id,
+        buyer: order.buyer,
+        seller: order.seller,
+        machine_id: order.machine_id,
+        old_status,
+        new_status: order.status.clone(),
+        price: order.price,
+        duration: order.duration,
+        seller_amount,
+        buyer_amount,
+        fee,
+        timestamp: now_ts,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(order_id: [u8; 16])]
+pub struct ReserveOrder<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(mut)]
+    pub machine: Box<Account<'info, Machine>>,
+
+    #[account(
+        init_if_needed,
+        seeds = [b"calendar", machine.key().as_ref()],
+        bump,
+        payer = buyer,
+        space = 8 + Calendar::INIT_SPACE
+    )]
+    pub calendar: Box<Account<'info, Calendar>>,
+
+    #[account(
+        init,
+        seeds = [b"order", buyer.key().as_ref(), order_id.as_ref()],
+        bump,
+        payer = buyer,
+        space = 8 + Order::INIT_SPACE
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        seeds = [b"escrow", order.key().as_ref()],
+        bump,
+        payer = buyer,
+        token::mint = mint,
+        token::authority = escrow,
+        token::token_program = token_program
+    )]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // The payment mint must still be allowed by the admin
+    #[account(
+        seeds = [b"payment-mint", mint.key().as_ref()],
+        bump
+    )]
+    pub payment_mint: Box<Account<'info, PaymentMint>>,
+
+    #[account(
+        address = machine.mint
+    )]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ActivateReservation<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        constraint = machine.uuid == order.machine_id && machine.owner == order.seller
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    #[account(
+        mut,
+        seeds = [b"calendar", machine.key().as_ref()],
+        bump
+    )]
+    pub calendar: Box<Account<'info, Calendar>>,
+
+    #[account(mut)]
+    pub order: Box<Account<'info, Order>>,
+
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CancelReservation<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        constraint = machine.uuid == order.machine_id && machine.owner == order.seller
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    #[account(
+        mut,
+        seeds = [b"calendar", machine.key().as_ref()],
+        bump
+    )]
+    pub calendar: Box<Account<'info, Calendar>>,
+
+    #[account(
+        mut,
+        has_one = buyer
+    )]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Owner of the seller token account.
+    #[account(
+        address = order.seller
+    )]
+    pub seller: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = mint,
+        associated_token::authority = seller,
+        associated_token::token_program = token_program
+    )]
+    pub seller_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Owner of the treasury token account.
+    #[account(
+        address = config.treasury
+    )]
+    pub treasury: UncheckedAccount<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        associated_token::mint = mint,
+        associated_token::authority = treasury,
+        associated_token::token_program = token_program
+    )]
+    pub treasury_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", order.key().as_ref()],
+        bump
+    )]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        address = order.mint
+    )]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct OrderReservedEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub mint: Pubkey,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    pub units: u32,
+    pub reserved_time: i64,
+    /// The amount escrowed from the buyer.
+    pub paid_amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct OrderActivatedEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    pub units: u32,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ReservationCancelledEvent {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub old_status: OrderStatus,
+    pub new_status: OrderStatus,
+    pub price: u64,
+    pub duration: u32,
+    /// The cancellation fee paid to the seller, less the protocol fee.
+    pub seller_amount: u64,
+    pub buyer_amount: u64,
+    /// The protocol fee sent to the treasury.
+    pub fee: u64,
+    pub timestamp: i64,
+}
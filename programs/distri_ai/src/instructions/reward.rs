@@ -1,10 +1,10 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{transfer_checked, Mint, Token, TokenAccount, TransferChecked},
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
 };
-use crate::dist_token;
 use crate::errors::DistriAIError;
+use crate::state::config::*;
 use crate::state::machine::*;
 use crate::state::reward::*;
 
@@ -20,24 +20,107 @@ pub fn reward_pool_deposit(ctx: Context<RewardPoolDeposit>, amount: u64) -> Resu
         },
     );
     transfer_checked(cpi_context, amount, ctx.accounts.mint.decimals)?;
+
+    emit!(RewardPoolDepositedEvent {
+        depositor: ctx.accounts.signer.key(),
+        mint: ctx.accounts.mint.key(),
+        amount,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Locks the unit rewards of a closed period, anyone can call it.
+///
+/// The rewards of the period are added to the liabilities of the reward pool,
+/// which must hold enough tokens to pay every finalized period.
+pub fn finalize_period(ctx: Context<FinalizePeriod>, period: u32) -> Result<()> {
+    let config = &mut ctx.accounts.config;
+    require_gt!(
+        Reward::current_period(config)?,
+        period,
+        DistriAIError::InvalidPeriod
+    );
+
+    let reward = &mut ctx.accounts.reward;
+    require!(!reward.finalized, DistriAIError::PeriodFinalized);
+    // Both counts are at least 1 once the reward exists
+    if !reward.weighted {
+        reward.unit_periodic_reward =
+            Reward::periodic_pool(reward.pool) / u64::from(reward.machine_num.max(1));
+    }
+    reward.unit_task_reward = Reward::task_pool(reward.pool) / u64::from(reward.task_num.max(1));
+    let distributed = reward.distributed()?;
+    reward.remainder = reward
+        .pool
+        .checked_sub(distributed)
+        .ok_or(DistriAIError::MathOverflow)?;
+    reward.unclaimed = distributed;
+    reward.finalized = true;
+
+    config.reward_liabilities = config
+        .reward_liabilities
+        .checked_add(distributed)
+        .ok_or(DistriAIError::MathOverflow)?;
+    require_gte!(
+        ctx.accounts.reward_pool.amount,
+        config.reward_liabilities,
+        DistriAIError::InsufficientRewardPool
+    );
+
+    emit!(PeriodFinalizedEvent {
+        period,
+        pool: reward.pool,
+        machine_num: reward.machine_num,
+        task_num: reward.task_num,
+        total_weight: reward.total_weight,
+        unit_task_reward: reward.unit_task_reward,
+        remainder: reward.remainder,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
     Ok(())
 }
 
 /// Processes a reward claim for a specific period.
 pub fn claim(ctx: Context<Claim>, period: u32) -> Result<()> {
     require_gt!(
-        Reward::current_period()?,
+        Reward::current_period(&ctx.accounts.config)?,
         period,
         DistriAIError::InvalidPeriod
     );
 
-    let reward = &ctx.accounts.reward;
+    let reward = &mut ctx.accounts.reward;
+    require!(reward.finalized, DistriAIError::PeriodNotFinalized);
     let reward_machine = &mut ctx.accounts.reward_machine;
     require!(!reward_machine.claimed, DistriAIError::RepeatClaim);
     reward_machine.claimed = true;
 
+    let periodic_rewards = reward.periodic_reward(reward_machine.weight)?;
+    let task_rewards = reward
+        .unit_task_reward
+        .checked_mul(reward_machine.task_num.into())
+        .ok_or(DistriAIError::MathOverflow)?;
+    let total_rewards = periodic_rewards
+        .checked_add(task_rewards)
+        .ok_or(DistriAIError::MathOverflow)?;
     let machine = &mut ctx.accounts.machine;
-    machine.claimed_periodic_rewards = machine.claimed_periodic_rewards.saturating_add(reward.unit_periodic_reward);
+    machine.claimed_periodic_rewards = machine
+        .claimed_periodic_rewards
+        .checked_add(periodic_rewards)
+        .ok_or(DistriAIError::MathOverflow)?;
+    machine.claimed_task_rewards = machine
+        .claimed_task_rewards
+        .checked_add(task_rewards)
+        .ok_or(DistriAIError::MathOverflow)?;
+
+    // Periods finalized by migration were never added to the liabilities
+    let settled = total_rewards.min(reward.unclaimed);
+    reward.unclaimed -= settled;
+    let config = &mut ctx.accounts.config;
+    config.reward_liabilities = config
+        .reward_liabilities
+        .checked_sub(settled)
+        .ok_or(DistriAIError::MathOverflow)?;
 
     // Transfer token from reward pool to owner
     let mint_key = ctx.accounts.mint.key();
@@ -52,27 +135,37 @@ pub fn claim(ctx: Context<Claim>, period: u32) -> Result<()> {
         },
         signer,
     );
-    transfer_checked(cpi_context, reward.unit_periodic_reward, ctx.accounts.mint.decimals)?;
+    transfer_checked(cpi_context, total_rewards, ctx.accounts.mint.decimals)?;
 
-    emit!(RewardEvent {
+    emit!(RewardClaimedEvent {
         period: reward_machine.period,
         owner: reward_machine.owner,
         machine_id: reward_machine.machine_id,
+        periodic_reward: periodic_rewards,
+        task_reward: task_rewards,
+        timestamp: Clock::get()?.unix_timestamp,
     });
     Ok(())
 }
 
 #[derive(Accounts)]
 pub struct RewardPoolDeposit<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+
     #[account(mut)]
     pub signer: Signer<'info>,
 
     #[account(
         mut,
         associated_token::mint = mint,
-        associated_token::authority = signer
+        associated_token::authority = signer,
+        associated_token::token_program = token_program
     )]
-    pub signer_ata: Account<'info, TokenAccount>,
+    pub signer_ata: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         init_if_needed,
@@ -80,19 +173,46 @@ pub struct RewardPoolDeposit<'info> {
         bump,
         payer = signer,
         token::mint = mint,
-        token::authority = reward_pool
+        token::authority = reward_pool,
+        token::token_program = token_program
     )]
-    pub reward_pool: Account<'info, TokenAccount>,
+    pub reward_pool: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
-        address = dist_token::ID
+        address = config.mint
     )]
-    pub mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
+#[derive(Accounts)]
+#[instruction(period: u32)]
+pub struct FinalizePeriod<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", period.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub reward: Account<'info, Reward>,
+
+    #[account(
+        seeds = [b"reward-pool", config.mint.as_ref()],
+        bump
+    )]
+    pub reward_pool: InterfaceAccount<'info, TokenAccount>,
+
+    pub signer: Signer<'info>,
+}
+
 #[derive(Accounts)]
 #[instruction(period: u32)]
 pub struct Claim<'info> {
@@ -103,6 +223,14 @@ pub struct Claim<'info> {
     pub machine: Account<'info, Machine>,
 
     #[account(
+        mut,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
         seeds = [b"reward", period.to_le_bytes().as_ref()],
         bump
     )]
@@ -122,29 +250,55 @@ pub struct Claim<'info> {
         init_if_needed,
         payer = owner,
         associated_token::mint = mint,
-        associated_token::authority = owner
+        associated_token::authority = owner,
+        associated_token::token_program = token_program
     )]
-    pub owner_ata: Account<'info, TokenAccount>,
+    pub owner_ata: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
         seeds = [b"reward-pool", mint.key().as_ref()],
         bump
     )]
-    pub reward_pool: Account<'info, TokenAccount>,
+    pub reward_pool: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
-        address = dist_token::ID
+        address = config.mint
     )]
-    pub mint: Account<'info, Mint>,
-    pub token_program: Program<'info, Token>,
+    pub mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
 #[event]
-pub struct RewardEvent {
+pub struct RewardPoolDepositedEvent {
+    pub depositor: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PeriodFinalizedEvent {
+    pub period: u32,
+    pub pool: u64,
+    pub machine_num: u32,
+    pub task_num: u32,
+    /// The sum of the periodic reward weights of all machines.
+    pub total_weight: u64,
+    pub unit_task_reward: u64,
+    /// The part of the pool left undistributed by rounding.
+    pub remainder: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardClaimedEvent {
     pub period: u32,
     pub owner: Pubkey,
     pub machine_id: [u8; 16],
+    pub periodic_reward: u64,
+    pub task_reward: u64,
+    pub timestamp: i64,
 }
@@ -0,0 +1,337 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::AssociatedToken,
+    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+};
+use crate::errors::DistriAIError;
+use crate::state::config::*;
+use crate::state::machine::*;
+
+/// Bonds DIST to a machine, the stake is required to offer the machine for rent.
+pub fn stake_machine(ctx: Context<StakeMachine>, amount: u64) -> Result<()> {
+    let stake_before = ctx.accounts.stake_vault.amount;
+    let cpi_context = CpiContext::new(
+        ctx.accounts.token_program.to_account_info(),
+        TransferChecked {
+            from: ctx.accounts.owner_ata.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.stake_vault.to_account_info(),
+            authority: ctx.accounts.owner.to_account_info(),
+        },
+    );
+    transfer_checked(cpi_context, amount, ctx.accounts.mint.decimals)?;
+
+    // Record the amount actually received, transfer fees are paid by the owner
+    ctx.accounts.stake_vault.reload()?;
+    let amount = ctx
+        .accounts
+        .stake_vault
+        .amount
+        .checked_sub(stake_before)
+        .ok_or(DistriAIError::MathOverflow)?;
+
+    let machine = &mut ctx.accounts.machine;
+    machine.stake = machine
+        .stake
+        .checked_add(amount)
+        .ok_or(DistriAIError::MathOverflow)?;
+
+    emit!(MachineStakedEvent {
+        owner: machine.owner,
+        uuid: machine.uuid,
+        amount,
+        stake: machine.stake,
+        timestamp: Clock::get()?.unix_timestamp,
+    });
+    Ok(())
+}
+
+/// Starts unbonding part of a machine's stake, it stays slashable until the unbonding period ends.
+pub fn unstake_machine(ctx: Context<UnstakeMachine>, amount: u64) -> Result<()> {
+    let machine = &mut ctx.accounts.machine;
+    require_gte!(machine.stake, amount, DistriAIError::InsufficientStake);
+    machine.stake -= amount;
+    // An offered or rented machine must keep the minimum stake
+    if machine.status != MachineStatus::Idle || machine.allocated > 0 {
+        require_gte!(
+            machine.stake,
+            ctx.accounts.config.min_stake,
+            DistriAIError::InsufficientStake
+        );
+    }
+
+    // Unstaking again restarts the unbonding period of the whole unbonding stake
+    let now_ts = Clock::get()?.unix_timestamp;
+    machine.unbonding_stake = machine
+        .unbonding_stake
+        .checked_add(amount)
+        .ok_or(DistriAIError::MathOverflow)?;
+    machine.unbonding_time = now_ts
+        .checked_add(ctx.accounts.config.unbonding_period)
+        .ok_or(DistriAIError::MathOverflow)?;
+
+    emit!(MachineUnstakedEvent {
+        owner: machine.owner,
+        uuid: machine.uuid,
+        amount,
+        stake: machine.stake,
+        unbonding_stake: machine.unbonding_stake,
+        unbonding_time: machine.unbonding_time,
+        timestamp: now_ts,
+    });
+    Ok(())
+}
+
+/// Withdraws the unbonded stake of a machine to its owner.
+pub fn withdraw_stake(ctx: Context<WithdrawStake>) -> Result<()> {
+    let machine_key = ctx.accounts.machine.key();
+    let machine = &mut ctx.accounts.machine;
+    let now_ts = Clock::get()?.unix_timestamp;
+    require_gte!(now_ts, machine.unbonding_time, DistriAIError::StakeUnbonding);
+    let amount = machine.unbonding_stake;
+    machine.unbonding_stake = 0;
+
+    let signer: &[&[&[u8]]] = &[&[b"stake", machine_key.as_ref(), &[ctx.bumps.stake_vault]]];
+    transfer_from_stake(
+        &ctx.accounts.token_program,
+        &ctx.accounts.stake_vault,
+        &ctx.accounts.mint,
+        ctx.accounts.owner_ata.to_account_info(),
+        signer,
+        amount,
+    )?;
+
+    emit!(StakeWithdrawnEvent {
+        owner: machine.owner,
+        uuid: machine.uuid,
+        amount,
+        timestamp: now_ts,
+    });
+    Ok(())
+}
+
+/// Transfers `amount` of DIST out of a machine stake vault.
+pub(crate) fn transfer_from_stake<'info>(
+    token_program: &Interface<'info, TokenInterface>,
+    stake_vault: &InterfaceAccount<'info, TokenAccount>,
+    mint: &InterfaceAccount<'info, Mint>,
+    to: AccountInfo<'info>,
+    signer: &[&[&[u8]]],
+    amount: u64,
+) -> Result<()> {
+    let cpi_context = CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        TransferChecked {
+            from: stake_vault.to_account_info(),
+            mint: mint.to_account_info(),
+            to,
+            authority: stake_vault.to_account_info(),
+        },
+        signer,
+    );
+    transfer_checked(cpi_context, amount, mint.decimals)
+}
+
+/// The token accounts a machine stake is slashed with.
+pub(crate) struct SlashAccounts<'a, 'info> {
+    pub token_program: &'a Interface<'info, TokenInterface>,
+    pub stake_vault: &'a InterfaceAccount<'info, TokenAccount>,
+    pub stake_mint: &'a InterfaceAccount<'info, Mint>,
+    pub buyer_stake_ata: AccountInfo<'info>,
+    pub treasury_stake_ata: AccountInfo<'info>,
+}
+
+/// Slashes a machine stake for a failed or expired order, compensating the buyer
+/// and sending the rest to the treasury.
+pub(crate) fn slash_stake<'info>(
+    config: &Config,
+    machine: &mut Account<'info, Machine>,
+    order_id: [u8; 16],
+    stake_bump: u8,
+    accounts: SlashAccounts<'_, 'info>,
+    now_ts: i64,
+) -> Result<()> {
+    let machine_key = machine.key();
+    let amount = machine.slash(config.slash_bps)?;
+    let buyer_amount = Config::bps_of(amount, config.slash_compensation_bps);
+    let treasury_amount = amount - buyer_amount;
+    let signer: &[&[&[u8]]] = &[&[b"stake", machine_key.as_ref(), &[stake_bump]]];
+    if buyer_amount > 0 {
+        transfer_from_stake(
+            accounts.token_program,
+            accounts.stake_vault,
+            accounts.stake_mint,
+            accounts.buyer_stake_ata,
+            signer,
+            buyer_amount,
+        )?;
+    }
+    if treasury_amount > 0 {
+        transfer_from_stake(
+            accounts.token_program,
+            accounts.stake_vault,
+            accounts.stake_mint,
+            accounts.treasury_stake_ata,
+            signer,
+            treasury_amount,
+        )?;
+    }
+
+    emit!(MachineSlashedEvent {
+        owner: machine.owner,
+        uuid: machine.uuid,
+        order_id,
+        amount,
+        buyer_amount,
+        treasury_amount,
+        stake: machine.stake,
+        unbonding_stake: machine.unbonding_stake,
+        timestamp: now_ts,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct StakeMachine<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        associated_token::mint = mint,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program
+    )]
+    pub owner_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init_if_needed,
+        seeds = [b"stake", machine.key().as_ref()],
+        bump,
+        payer = owner,
+        token::mint = mint,
+        token::authority = stake_vault,
+        token::token_program = token_program
+    )]
+    pub stake_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        address = config.mint
+    )]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UnstakeMachine<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawStake<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(
+        mut,
+        has_one = owner
+    )]
+    pub machine: Box<Account<'info, Machine>>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        associated_token::mint = mint,
+        associated_token::authority = owner,
+        associated_token::token_program = token_program
+    )]
+    pub owner_ata: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"stake", machine.key().as_ref()],
+        bump
+    )]
+    pub stake_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        address = config.mint
+    )]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct MachineStakedEvent {
+    pub owner: Pubkey,
+    pub uuid: [u8; 16],
+    pub amount: u64,
+    pub stake: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MachineUnstakedEvent {
+    pub owner: Pubkey,
+    pub uuid: [u8; 16],
+    pub amount: u64,
+    pub stake: u64,
+    pub unbonding_stake: u64,
+    pub unbonding_time: i64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct StakeWithdrawnEvent {
+    pub owner: Pubkey,
+    pub uuid: [u8; 16],
+    pub amount: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MachineSlashedEvent {
+    pub owner: Pubkey,
+    pub uuid: [u8; 16],
+    pub order_id: [u8; 16],
+    pub amount: u64,
+    pub buyer_amount: u64,
+    pub treasury_amount: u64,
+    pub stake: u64,
+    pub unbonding_stake: u64,
+    pub timestamp: i64,
+}
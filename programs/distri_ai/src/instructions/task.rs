@@ -1,8 +1,10 @@
 use anchor_lang::prelude::*;
 use crate::errors::DistriAIError;
+use crate::state::config::*;
 use crate::state::machine::*;
 use crate::state::reward::*;
 use crate::state::task::*;
+use crate::state::Versioned;
 
 // submit_task is a public function to submit a new task to the system.
 pub fn submit_task(
@@ -21,9 +23,13 @@ pub fn submit_task(
         machine.status == MachineStatus::ForRent,
         DistriAIError::IncorrectStatus
     );
+    require!(
+        machine.is_reward_eligible(&ctx.accounts.config),
+        DistriAIError::MachineNotEligible
+    );
     require_eq!(
         period,
-        Reward::current_period()?,
+        Reward::current_period(&ctx.accounts.config)?,
         DistriAIError::InvalidPeriod
     );
 
@@ -33,30 +39,63 @@ pub fn submit_task(
     task.owner = ctx.accounts.owner.key();
     task.machine_id = machine.uuid;
     task.metadata = metadata;
+    task.version = Task::VERSION;
 
     let reward_machine = &mut ctx.accounts.reward_machine;
     reward_machine.period = period;
     reward_machine.owner = machine.owner;
     reward_machine.machine_id = machine.uuid;
-    reward_machine.task_num = reward_machine.task_num.saturating_add(1);
+    reward_machine.version = RewardMachine::VERSION;
+    reward_machine.task_num = reward_machine
+        .task_num
+        .checked_add(1)
+        .ok_or(DistriAIError::MathOverflow)?;
+    let old_weight = reward_machine.weight;
+    reward_machine.weight = RewardMachine::weight(
+        machine.score,
+        reward_machine.task_num,
+        machine.completed_count,
+    )?;
 
     let reward = &mut ctx.accounts.reward;
+    require!(!reward.finalized, DistriAIError::PeriodFinalized);
     reward.period = period;
     if reward.start_time == 0 {
-        reward.start_time = Reward::start_time(period);
-        reward.pool = Reward::pool(period);
+        reward.start_time = Reward::start_time(&ctx.accounts.config, period)?;
+        reward.pool = Reward::pool(period)?;
+        reward.version = Reward::VERSION;
+        reward.weighted = true;
     }
     if reward_machine.task_num == 1 {
-        reward.machine_num = reward.machine_num.saturating_add(1);
+        reward.machine_num = reward
+            .machine_num
+            .checked_add(1)
+            .ok_or(DistriAIError::MathOverflow)?;
+    }
+    reward.task_num = reward
+        .task_num
+        .checked_add(1)
+        .ok_or(DistriAIError::MathOverflow)?;
+    // The machine weight is refreshed with its latest score and uptime on every task,
+    // a migrated period keeps sharing its pool equally until it is finalized
+    if reward.weighted {
+        reward.total_weight = reward
+            .total_weight
+            .checked_sub(old_weight)
+            .and_then(|total_weight| total_weight.checked_add(reward_machine.weight))
+            .ok_or(DistriAIError::MathOverflow)?;
     }
-    reward.unit_periodic_reward = reward.pool.saturating_div(reward.machine_num.into());
-    reward.task_num = reward.task_num.saturating_add(1);
+    // The task count is at least 1 here
+    reward.unit_task_reward = Reward::task_pool(reward.pool) / u64::from(reward.task_num);
 
-    emit!(TaskEvent {
+    emit!(TaskSubmittedEvent {
         uuid: task.uuid,
         period: task.period,
         owner: task.owner,
         machine_id: task.machine_id,
+        machine_task_num: reward_machine.task_num,
+        period_task_num: reward.task_num,
+        timestamp: Clock::get()?.unix_timestamp,
     });
     Ok(())
 }
@@ -70,6 +109,12 @@ pub struct SubmitTask<'info> {
     )]
     pub machine: Account<'info, Machine>,
 
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+
     #[account(
         init,
         seeds = [b"task", owner.key().as_ref(), uuid.as_ref()],
@@ -104,9 +149,14 @@ pub struct SubmitTask<'info> {
 }
 
 #[event]
-pub struct TaskEvent {
+pub struct TaskSubmittedEvent {
     pub uuid: [u8; 16],
     pub period: u32,
     pub owner: Pubkey,
     pub machine_id: [u8; 16],
+    /// Tasks submitted by this machine in the period.
+    pub machine_task_num: u32,
+    /// Tasks submitted by all machines in the period.
+    pub period_task_num: u32,
+    pub timestamp: i64,
 }
@@ -1,6 +1,8 @@
 use anchor_lang::prelude::*;
 use instructions::*;
 use migration::*;
+use state::config::ConfigParams;
+use state::machine::MachineSpec;
 
 pub mod errors;
 pub mod instructions;
@@ -11,15 +13,30 @@ pub mod state;
 // automatically when you build the project.
 declare_id!("6yFTDdiS1W9T9yg6YejkwKggkEE4NYqdSSzVqQvuLn16");
 
-pub mod dist_token {
-    use solana_program::declare_id;
-    declare_id!("896KfVVY6VRGQs1d9CKLnKUEgXXCCJcEEg7LwSK84vWE");
-}
-
 #[program]
 mod distri_ai {
     use super::*;
 
+    pub fn initialize(ctx: Context<Initialize>, params: ConfigParams) -> Result<()> {
+        instructions::config::initialize(ctx, params)
+    }
+
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        new_admin: Pubkey,
+        params: ConfigParams,
+    ) -> Result<()> {
+        instructions::config::update_config(ctx, new_admin, params)
+    }
+
+    pub fn add_payment_mint(ctx: Context<AddPaymentMint>) -> Result<()> {
+        instructions::config::add_payment_mint(ctx)
+    }
+
+    pub fn remove_payment_mint(ctx: Context<RemovePaymentMint>) -> Result<()> {
+        instructions::config::remove_payment_mint(ctx)
+    }
+
     pub fn add_machine(ctx: Context<AddMachine>, uuid: [u8; 16], metadata: String) -> Result<()> {
         instructions::machine::add_machine(ctx, uuid, metadata)
     }
@@ -28,19 +45,66 @@ mod distri_ai {
         instructions::machine::remove_machine(ctx)
     }
 
+    pub fn verify_machine(ctx: Context<VerifyMachine>, verified: bool, score: u8) -> Result<()> {
+        instructions::machine::verify_machine(ctx, verified, score)
+    }
+
+    pub fn heartbeat(ctx: Context<Heartbeat>) -> Result<()> {
+        instructions::machine::heartbeat(ctx)
+    }
+
+    pub fn update_machine_metadata(
+        ctx: Context<UpdateMachineMetadata>,
+        metadata: String,
+        spec: MachineSpec,
+    ) -> Result<()> {
+        instructions::machine::update_machine_metadata(ctx, metadata, spec)
+    }
+
     pub fn make_offer(
         ctx: Context<MakeOffer>,
         price: u64,
         max_duration: u32,
         disk: u32,
+        billing_granularity: u32,
+        capacity: u32,
+    ) -> Result<()> {
+        instructions::machine::make_offer(
+            ctx,
+            price,
+            max_duration,
+            disk,
+            billing_granularity,
+            capacity,
+        )
+    }
+
+    pub fn update_offer(
+        ctx: Context<UpdateOffer>,
+        price: u64,
+        max_duration: u32,
+        disk: u32,
+        effective_time: Option<i64>,
     ) -> Result<()> {
-        instructions::machine::make_offer(ctx, price, max_duration, disk)
+        instructions::machine::update_offer(ctx, price, max_duration, disk, effective_time)
     }
 
     pub fn cancel_offer(ctx: Context<CancelOffer>) -> Result<()> {
         instructions::machine::cancel_offer(ctx)
     }
 
+    pub fn stake_machine(ctx: Context<StakeMachine>, amount: u64) -> Result<()> {
+        instructions::stake::stake_machine(ctx, amount)
+    }
+
+    pub fn unstake_machine(ctx: Context<UnstakeMachine>, amount: u64) -> Result<()> {
+        instructions::stake::unstake_machine(ctx, amount)
+    }
+
+    pub fn withdraw_stake(ctx: Context<WithdrawStake>) -> Result<()> {
+        instructions::stake::withdraw_stake(ctx)
+    }
+
     // submit_task is a public function that submits a task to the system.
     pub fn submit_task(
         ctx: Context<SubmitTask>,
@@ -55,6 +119,10 @@ mod distri_ai {
         instructions::reward::reward_pool_deposit(ctx, amount)
     }
 
+    pub fn finalize_period(ctx: Context<FinalizePeriod>, period: u32) -> Result<()> {
+        instructions::reward::finalize_period(ctx, period)
+    }
+
     pub fn claim(ctx: Context<Claim>, period: u32) -> Result<()> {
         instructions::reward::claim(ctx, period)
     }
@@ -63,13 +131,46 @@ mod distri_ai {
         ctx: Context<PlaceOrder>,
         order_id: [u8; 16],
         duration: u32,
+        units: u32,
         metadata: String,
+        max_price: u64,
+        expected_machine_owner: Pubkey,
     ) -> Result<()> {
-        instructions::order::place_order(ctx, order_id, duration, metadata)
+        instructions::order::place_order(
+            ctx,
+            order_id,
+            duration,
+            units,
+            metadata,
+            max_price,
+            expected_machine_owner,
+        )
     }
 
-    pub fn renew_order(ctx: Context<RenewOrder>, duration: u32) -> Result<()> {
-        instructions::order::renew_order(ctx, duration)
+    pub fn renew_order(
+        ctx: Context<RenewOrder>,
+        duration: u32,
+        max_price: u64,
+        expected_machine_owner: Pubkey,
+    ) -> Result<()> {
+        instructions::order::renew_order(ctx, duration, max_price, expected_machine_owner)
+    }
+
+    pub fn reserve_order(
+        ctx: Context<ReserveOrder>,
+        order_id: [u8; 16],
+        metadata: String,
+        params: ReservationParams,
+    ) -> Result<()> {
+        instructions::reservation::reserve_order(ctx, order_id, metadata, params)
+    }
+
+    pub fn activate_reservation(ctx: Context<ActivateReservation>) -> Result<()> {
+        instructions::reservation::activate_reservation(ctx)
+    }
+
+    pub fn cancel_reservation(ctx: Context<CancelReservation>) -> Result<()> {
+        instructions::reservation::cancel_reservation(ctx)
     }
 
     pub fn start_order(ctx: Context<StartOrder>) -> Result<()> {
@@ -80,18 +181,46 @@ mod distri_ai {
         instructions::order::refund_order(ctx)
     }
 
-    pub fn order_completed(
-        ctx: Context<OrderCompleted>,
-        metadata: String,
-        score: u8,
-    ) -> Result<()> {
-        instructions::order::order_completed(ctx, metadata, score)
+    pub fn order_completed(ctx: Context<OrderCompleted>, metadata: String) -> Result<()> {
+        instructions::order::order_completed(ctx, metadata)
+    }
+
+    pub fn release_payment(ctx: Context<ReleasePayment>) -> Result<()> {
+        instructions::order::release_payment(ctx)
     }
 
     pub fn order_failed(ctx: Context<OrderFailed>, metadata: String) -> Result<()> {
         instructions::order::order_failed(ctx, metadata)
     }
 
+    pub fn expire_order(ctx: Context<ExpireOrder>) -> Result<()> {
+        instructions::order::expire_order(ctx)
+    }
+
+    pub fn settle_order(ctx: Context<SettleOrder>) -> Result<()> {
+        instructions::order::settle_order(ctx)
+    }
+
+    pub fn rate_order(ctx: Context<RateOrder>, rating: u8) -> Result<()> {
+        instructions::order::rate_order(ctx, rating)
+    }
+
+    pub fn open_dispute(ctx: Context<OpenDispute>, evidence: String) -> Result<()> {
+        instructions::dispute::open_dispute(ctx, evidence)
+    }
+
+    pub fn submit_evidence(ctx: Context<SubmitEvidence>, evidence: String) -> Result<()> {
+        instructions::dispute::submit_evidence(ctx, evidence)
+    }
+
+    pub fn resolve_dispute(ctx: Context<ResolveDispute>, buyer_amount: u64) -> Result<()> {
+        instructions::dispute::resolve_dispute(ctx, buyer_amount)
+    }
+
+    pub fn expire_dispute(ctx: Context<ExpireDispute>) -> Result<()> {
+        instructions::dispute::expire_dispute(ctx)
+    }
+
     pub fn remove_order(ctx: Context<RemoveOrder>) -> Result<()> {
         instructions::order::remove_order(ctx)
     }
@@ -129,19 +258,11 @@ mod distri_ai {
     }
 
     // --------------------------------- migration ----------------------------------
-    pub fn migrate_machine_new(ctx: Context<MigrationMachineNew>) -> Result<()> {
-        migration::machine::migrate_machine_new(ctx)
-    }
-
-    pub fn migrate_machine_rename(ctx: Context<MigrationMachineRename>) -> Result<()> {
-        migration::machine::migrate_machine_rename(ctx)
-    }
-
-    pub fn migrate_order_new(ctx: Context<MigrationOrderNew>) -> Result<()> {
-        migration::order::migrate_order_new(ctx)
+    pub fn migrate_account(ctx: Context<MigrateAccount>) -> Result<()> {
+        migration::migrate_account(ctx)
     }
 
-    pub fn migrate_order_rename(ctx: Context<MigrationOrderRename>) -> Result<()> {
-        migration::order::migrate_order_rename(ctx)
+    pub fn migrate_escrow(ctx: Context<MigrateEscrow>) -> Result<()> {
+        migration::migrate_escrow(ctx)
     }
 }
@@ -0,0 +1,96 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{
+    transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+use crate::errors::DistriAIError;
+use crate::state::config::*;
+use crate::state::order::*;
+
+/// Moves the payment of an order placed before per-order escrows from the shared vault
+/// into a new escrow of the order, so it can be settled like any other order.
+pub fn migrate_escrow(ctx: Context<MigrateEscrow>) -> Result<()> {
+    let signer = ctx.accounts.signer.key();
+    let order = &mut ctx.accounts.order;
+    require!(
+        signer == ctx.accounts.config.admin || signer == order.buyer,
+        DistriAIError::Unauthorized
+    );
+    // Only active orders still hold funds in the vault, later orders already have an escrow
+    require!(
+        order.status == OrderStatus::Preparing || order.status == OrderStatus::Training,
+        DistriAIError::IncorrectStatus
+    );
+
+    // Transfer token from vault to escrow
+    let mint_key = ctx.accounts.mint.key();
+    let vault_signer: &[&[&[u8]]] = &[&[b"vault", mint_key.as_ref(), &[ctx.bumps.vault]]];
+    let cpi_context = CpiContext::new_with_signer(
+        ctx.accounts.token_program.to_account_info(),
+        TransferChecked {
+            from: ctx.accounts.vault.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.escrow.to_account_info(),
+            authority: ctx.accounts.vault.to_account_info(),
+        },
+        vault_signer,
+    );
+    transfer_checked(cpi_context, order.total, ctx.accounts.mint.decimals)?;
+
+    // Record the amount actually received, so the order settles with what its escrow holds
+    ctx.accounts.escrow.reload()?;
+    order.total = ctx.accounts.escrow.amount;
+
+    emit!(EscrowMigrationEvent {
+        order: order.key(),
+        amount: order.total,
+    });
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct MigrateEscrow<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Box<Account<'info, Config>>,
+
+    #[account(mut)]
+    pub order: Box<Account<'info, Order>>,
+
+    #[account(
+        init,
+        seeds = [b"escrow", order.key().as_ref()],
+        bump,
+        payer = signer,
+        token::mint = mint,
+        token::authority = escrow,
+        token::token_program = token_program
+    )]
+    pub escrow: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // The vault shared by all orders before per-order escrows
+    #[account(
+        mut,
+        seeds = [b"vault", mint.key().as_ref()],
+        bump
+    )]
+    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        address = order.mint
+    )]
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct EscrowMigrationEvent {
+    pub order: Pubkey,
+    pub amount: u64,
+}
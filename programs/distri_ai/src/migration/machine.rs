@@ -1,96 +1,243 @@
 use anchor_lang::prelude::*;
+use crate::migration::{decode, Migrate};
+use crate::state::config::*;
 use crate::state::machine::*;
+use crate::state::Versioned;
 
-// Define a public function to handle the migration of machine data to a new structure
-pub fn migrate_machine_new(ctx: Context<MigrationMachineNew>) -> Result<()> {
-    let machine_before = &mut ctx.accounts.machine_before;
-    let machine_after = &mut ctx.accounts.machine_after;
-    machine_after.owner = machine_before.owner;
-    machine_after.uuid = machine_before.uuid;
-    machine_after.metadata = machine_before.metadata.clone();
-    machine_after.status = machine_before.status.clone();
-    machine_after.price = machine_before.price;
-    machine_after.max_duration = machine_before.max_duration;
-    machine_after.disk = machine_before.disk;
-    machine_after.completed_count = machine_before.completed_count;
-    machine_after.failed_count = machine_before.failed_count;
-    machine_after.score = machine_before.score;
-    machine_after.claimed_periodic_rewards = machine_before.claimed_periodic_rewards;
-    machine_after.claimed_task_rewards = machine_before.claimed_task_rewards;
-
-    Ok(())
+/// Layout of `Machine` accounts before versioning.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace)]
+pub struct MachineV0 {
+    pub owner: Pubkey,
+    pub uuid: [u8; 16],
+    #[max_len(2048)]
+    pub metadata: String,
+    pub status: MachineStatus,
+    pub price: u64,
+    pub max_duration: u32,
+    pub disk: u32,
+    pub completed_count: u32,
+    pub failed_count: u32,
+    pub score: u8,
+    pub claimed_periodic_rewards: u64,
+    pub claimed_task_rewards: u64,
+    pub order_pda: Pubkey,
 }
 
-// Public function to handle the migration of a machine's data with a name change
-pub fn migrate_machine_rename(ctx: Context<MigrationMachineRename>) -> Result<()> {
-    let machine_before = &mut ctx.accounts.machine_before;
-    let machine_after = &mut ctx.accounts.machine_after;
-    machine_after.owner = machine_before.owner;
-    machine_after.uuid = machine_before.uuid;
-    machine_after.metadata = machine_before.metadata.clone();
-    machine_after.status = machine_before.status.clone();
-    machine_after.price = machine_before.price;
-    machine_after.max_duration = machine_before.max_duration;
-    machine_after.disk = machine_before.disk;
-    machine_after.completed_count = machine_before.completed_count;
-    machine_after.failed_count = machine_before.failed_count;
-    machine_after.score = machine_before.score;
-    machine_after.claimed_periodic_rewards = machine_before.claimed_periodic_rewards;
-    machine_after.claimed_task_rewards = machine_before.claimed_task_rewards;
-    machine_after.order_pda = machine_before.order_pda;
-    
-    Ok(())
+impl From<MachineV0> for Machine {
+    fn from(machine: MachineV0) -> Self {
+        Machine {
+            owner: machine.owner,
+            uuid: machine.uuid,
+            metadata: machine.metadata,
+            status: machine.status,
+            price: machine.price,
+            max_duration: machine.max_duration,
+            disk: machine.disk,
+            completed_count: machine.completed_count,
+            failed_count: machine.failed_count,
+            score: machine.score,
+            claimed_periodic_rewards: machine.claimed_periodic_rewards,
+            claimed_task_rewards: machine.claimed_task_rewards,
+            order_pda: machine.order_pda,
+            version: 0,
+            mint: Pubkey::default(),
+            stake: 0,
+            unbonding_stake: 0,
+            unbonding_time: 0,
+            rating_sum: 0,
+            rating_count: 0,
+            billing_granularity: 0,
+            pending_offer: None,
+            spec: MachineSpec::default(),
+            capacity: 0,
+            allocated: 0,
+            last_seen: 0,
+            verified: false,
+            open_orders: 0,
+        }
+    }
 }
 
-/// Defines a structure for migrating a machine account to a new version.
-///
-/// The `Accounts` macro is used to automatically handle account de/serialization and provide
-/// convenient access to account information within the program.
-///
-/// `'info` is a lifetime specifier that indicates the data lives as long as the program execution.
-#[derive(Accounts)]
-pub struct MigrationMachineNew<'info> {
-    #[account(
-        mut,
-        close = signer
-    )]
-    pub machine_before: Account<'info, Machine>,
+impl Migrate for Machine {
+    fn migrate(data: &[u8], config: &Config) -> Result<Self> {
+        let mut machine = decode::<Machine, MachineV0>(data)?;
+        // Fields appended after the version the machine was written with are decoded from
+        // leftover bytes, every one of them is reset
+        // Machines offered before multi-currency pricing are priced in the config mint
+        if machine.version < 2 {
+            machine.mint = config.mint;
+        }
+        if machine.version < 3 {
+            machine.stake = 0;
+            machine.unbonding_stake = 0;
+            machine.unbonding_time = 0;
+        }
+        if machine.version < 4 {
+            machine.rating_sum = 0;
+            machine.rating_count = 0;
+        }
+        // Machines offered before configurable granularity are billed by whole billing periods
+        if machine.version < 5 {
+            machine.billing_granularity = config.billing_granularity()?;
+        }
+        if machine.version < 6 {
+            machine.pending_offer = None;
+        }
+        if machine.version < 7 {
+            machine.spec = MachineSpec::default();
+        }
+        // Machines offered before multi-tenant rentals have a single unit, taken while renting
+        if machine.version < 8 {
+            machine.capacity = 1;
+            machine.allocated = u32::from(machine.status == MachineStatus::Renting);
+        }
+        if machine.version < 9 {
+            machine.last_seen = 0;
+        }
+        if machine.version < 10 {
+            machine.verified = false;
+        }
+        // Orders placed before open orders were counted are left out, active ones still hold capacity
+        if machine.version < 11 {
+            machine.open_orders = 0;
+        }
+        machine.version = Machine::VERSION;
+        Ok(machine)
+    }
+
+    fn authority(&self) -> Pubkey {
+        self.owner
+    }
+}
 
-    #[account(
-        init,
-        seeds = [b"machine-new", machine_before.owner.as_ref(), machine_before.uuid.as_ref()],
-        bump,
-        payer = signer,
-        space = 8 + MachineNew::INIT_SPACE
-    )]
-    pub machine_after: Account<'info, MachineNew>,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::DistriAIError;
+    use crate::migration::tests::migrate_legacy;
+    use anchor_lang::Discriminator;
 
-    #[account(mut)]
-    pub signer: Signer<'info>,
+    #[test]
+    fn migrate_v0_keeps_every_field() {
+        let before = MachineV0 {
+            owner: Pubkey::new_unique(),
+            uuid: [1; 16],
+            metadata: "{\"gpu\":\"4090\"}".to_string(),
+            status: MachineStatus::Renting,
+            price: 10_000_000_000,
+            max_duration: 100,
+            disk: 1000,
+            completed_count: 7,
+            failed_count: 2,
+            score: 88,
+            claimed_periodic_rewards: 123,
+            claimed_task_rewards: 456,
+            order_pda: Pubkey::new_unique(),
+        };
+        let config = Config {
+            mint: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        let after = migrate_legacy(&before, |data| Machine::migrate(data, &config));
+        assert_eq!(after.mint, config.mint);
+    }
 
-    pub system_program: Program<'info, System>,
-}
+    #[test]
+    fn migrate_older_version_fills_appended_fields() {
+        // A machine written with version 4, before configurable granularity
+        let before = Machine {
+            owner: Pubkey::new_unique(),
+            uuid: [1; 16],
+            metadata: "{}".to_string(),
+            status: MachineStatus::ForRent,
+            price: 10,
+            version: 4,
+            mint: Pubkey::new_unique(),
+            stake: 500,
+            rating_sum: 9,
+            rating_count: 2,
+            ..Default::default()
+        };
+        let mut data = Machine::DISCRIMINATOR.to_vec();
+        before.serialize(&mut data).unwrap();
+        let spec = MachineSpec::default().try_to_vec().unwrap().len();
+        data.truncate(data.len() - (4 + 1 + spec + 4 + 4 + 8 + 1 + 4));
+        let appended =
+            4 + 1 + PendingOffer::INIT_SPACE + MachineSpec::INIT_SPACE + 4 + 4 + 8 + 1 + 4;
+        data.resize(8 + Machine::INIT_SPACE - appended, 0);
 
-// Define a structure for the accounts involved in the migration process to rename a machine
-#[derive(Accounts)]
-pub struct MigrationMachineRename<'info> {
-    #[account(
-        mut,
-        close = signer
-    )]
-    pub machine_before: Account<'info, MachineNew>,
+        let config = Config {
+            mint: Pubkey::new_unique(),
+            billing_period: 3600,
+            ..Default::default()
+        };
+        let after = Machine::migrate(&data, &config).unwrap();
+        assert_eq!(after.version(), Machine::VERSION);
+        assert_eq!(after.mint, before.mint);
+        assert_eq!(after.stake, before.stake);
+        assert_eq!(after.rating_count, before.rating_count);
+        assert_eq!(after.billing_granularity, 3600);
+        assert_eq!(after.capacity, 1);
+        assert_eq!(after.allocated, 0);
 
-    #[account(
-        init,
-        seeds = [b"machine", machine_before.owner.as_ref(), machine_before.uuid.as_ref()],
-        bump,
-        payer = signer,
-        space = 8 + Machine::INIT_SPACE
-    )]
-    pub machine_after: Account<'info, Machine>,
+        // Accounts from a newer program are rejected
+        let mut newer = vec![0u8; 8 + Machine::INIT_SPACE];
+        Machine {
+            version: Machine::VERSION + 1,
+            ..Default::default()
+        }
+        .try_serialize(&mut &mut newer[..])
+        .unwrap();
+        assert_eq!(
+            Machine::migrate(&newer, &config).err(),
+            Some(DistriAIError::UnsupportedVersion.into())
+        );
+    }
 
-    #[account(mut)]
-    pub signer: Signer<'info>,
+    #[test]
+    fn migrate_older_version_resets_leftover_appended_fields() {
+        // A version 4 machine whose metadata shrank, leaving old bytes where later fields decode
+        let before = Machine {
+            owner: Pubkey::new_unique(),
+            status: MachineStatus::ForRent,
+            version: 4,
+            stake: 500,
+            rating_count: 2,
+            billing_granularity: 7,
+            pending_offer: Some(PendingOffer {
+                price: 1,
+                max_duration: 2,
+                disk: 3,
+                effective_time: 4,
+            }),
+            spec: MachineSpec {
+                gpu_count: 8,
+                ..Default::default()
+            },
+            capacity: 5,
+            allocated: 3,
+            last_seen: 9,
+            verified: true,
+            open_orders: 2,
+            ..Default::default()
+        };
+        let mut data = vec![0u8; 8 + Machine::INIT_SPACE];
+        before.try_serialize(&mut &mut data[..]).unwrap();
 
-    pub system_program: Program<'info, System>,
+        let config = Config {
+            billing_period: 3600,
+            ..Default::default()
+        };
+        let after = Machine::migrate(&data, &config).unwrap();
+        assert_eq!(after.stake, before.stake);
+        assert_eq!(after.rating_count, before.rating_count);
+        assert_eq!(after.billing_granularity, 3600);
+        assert!(after.pending_offer.is_none());
+        assert_eq!(after.spec.gpu_count, 0);
+        assert_eq!(after.capacity, 1);
+        assert_eq!(after.allocated, 0);
+        assert_eq!(after.last_seen, 0);
+        assert!(!after.verified);
+        assert_eq!(after.open_orders, 0);
+    }
 }
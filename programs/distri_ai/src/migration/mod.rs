@@ -1,5 +1,182 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{transfer, Transfer};
+use anchor_lang::Discriminator;
+use crate::errors::DistriAIError;
+use crate::state::config::*;
+use crate::state::machine::*;
+use crate::state::order::*;
+use crate::state::reward::*;
+use crate::state::task::*;
+use crate::state::Versioned;
+pub use escrow::*;
 pub use machine::*;
 pub use order::*;
+pub use reward::*;
+pub use task::*;
 
+pub mod escrow;
 pub mod machine;
 pub mod order;
+pub mod reward;
+pub mod task;
+
+/// A versioned account that can be upgraded in place from its older layouts.
+pub trait Migrate: Versioned + AccountSerialize + AccountDeserialize + Discriminator + Space {
+    /// Decodes account data written with the current or any older layout,
+    /// filling fields missing from older layouts with defaults.
+    fn migrate(data: &[u8], config: &Config) -> Result<Self>;
+
+    /// The key allowed to migrate this account besides the admin.
+    fn authority(&self) -> Pubkey;
+}
+
+/// Upgrades a `Machine`, `Order`, `Reward`, `RewardMachine` or `Task` account to its current layout,
+/// growing the account in place if needed.
+pub fn migrate_account(ctx: Context<MigrateAccount>) -> Result<()> {
+    let mut discriminator = [0u8; 8];
+    {
+        let data = ctx.accounts.account.try_borrow_data()?;
+        require_gte!(data.len(), 8, ErrorCode::AccountDiscriminatorNotFound);
+        discriminator.copy_from_slice(&data[..8]);
+    }
+
+    match discriminator {
+        Machine::DISCRIMINATOR => migrate::<Machine>(ctx),
+        Order::DISCRIMINATOR => migrate::<Order>(ctx),
+        Reward::DISCRIMINATOR => migrate::<Reward>(ctx),
+        RewardMachine::DISCRIMINATOR => migrate::<RewardMachine>(ctx),
+        Task::DISCRIMINATOR => migrate::<Task>(ctx),
+        _ => err!(ErrorCode::AccountDiscriminatorMismatch),
+    }
+}
+
+fn migrate<T: Migrate>(ctx: Context<MigrateAccount>) -> Result<()> {
+    let account = ctx.accounts.account.to_account_info();
+    let migrated = T::migrate(&account.try_borrow_data()?, &ctx.accounts.config)?;
+
+    let signer = ctx.accounts.signer.key();
+    require!(
+        signer == ctx.accounts.config.admin || signer == migrated.authority(),
+        DistriAIError::Unauthorized
+    );
+
+    // Top up rent and grow the account to the current layout size
+    let space = 8 + T::INIT_SPACE;
+    let lamports = Rent::get()?
+        .minimum_balance(space)
+        .saturating_sub(account.lamports());
+    if lamports > 0 {
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.signer.to_account_info(),
+                to: account.clone(),
+            },
+        );
+        transfer(cpi_context, lamports)?;
+    }
+    if account.data_len() < space {
+        account.realloc(space, true)?;
+    }
+    migrated.try_serialize(&mut &mut account.try_borrow_mut_data()?[..])?;
+
+    emit!(MigrationEvent {
+        account: account.key(),
+        version: migrated.version(),
+    });
+    Ok(())
+}
+
+/// Decodes `data` written with any layout of `T`.
+///
+/// Accounts written before versioning have the size of the legacy layout `L` and decode to
+/// version 0. Later layouts only append fields, so older versioned accounts are decoded with
+/// the current layout and keep the version they were written with. Their appended fields are
+/// decoded from padding or from bytes left behind when a string shrank, so `Migrate::migrate`
+/// must reset every field appended after that version.
+fn decode<T, L>(data: &[u8]) -> Result<T>
+where
+    T: AccountDeserialize + Discriminator + Space + Versioned,
+    L: AnchorDeserialize + Space + Into<T>,
+{
+    require!(
+        data.len() >= 8 && data[..8] == T::DISCRIMINATOR,
+        ErrorCode::AccountDiscriminatorMismatch
+    );
+    if data.len() == 8 + L::INIT_SPACE {
+        let legacy = L::deserialize(&mut &data[8..])?;
+        return Ok(legacy.into());
+    }
+
+    let mut padded = data.to_vec();
+    if padded.len() < 8 + T::INIT_SPACE {
+        padded.resize(8 + T::INIT_SPACE, 0);
+    }
+    let account = T::try_deserialize(&mut &padded[..])?;
+    require_gte!(
+        T::VERSION,
+        account.version(),
+        DistriAIError::UnsupportedVersion
+    );
+    Ok(account)
+}
+
+#[derive(Accounts)]
+pub struct MigrateAccount<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: The account type is detected from its discriminator and decoded in the instruction.
+    #[account(
+        mut,
+        owner = crate::ID
+    )]
+    pub account: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub signer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct MigrationEvent {
+    pub account: Pubkey,
+    pub version: u8,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Writes `before` as a legacy account of `T`, upgrades it with `migrate` and checks the legacy
+    /// fields keep their values and order and that migrating the upgraded account again is lossless.
+    pub(crate) fn migrate_legacy<T, L>(before: &L, migrate: impl Fn(&[u8]) -> Result<T>) -> T
+    where
+        T: Migrate,
+        L: AnchorSerialize + Space,
+    {
+        let fields = before.try_to_vec().unwrap();
+        let mut data = vec![0u8; 8 + L::INIT_SPACE];
+        data[..8].copy_from_slice(&T::DISCRIMINATOR);
+        data[8..8 + fields.len()].copy_from_slice(&fields);
+
+        let after = migrate(&data).unwrap();
+        assert_eq!(after.version(), T::VERSION);
+
+        // Older fields keep their values and order, new fields are appended
+        let mut migrated = vec![0u8; 8 + T::INIT_SPACE];
+        after.try_serialize(&mut &mut migrated[..]).unwrap();
+        assert_eq!(migrated[8..8 + fields.len()], fields[..]);
+
+        // Migrating the upgraded account again is lossless
+        let again = migrate(&migrated).unwrap();
+        let mut remigrated = vec![0u8; 8 + T::INIT_SPACE];
+        again.try_serialize(&mut &mut remigrated[..]).unwrap();
+        assert_eq!(migrated, remigrated);
+        after
+    }
+}
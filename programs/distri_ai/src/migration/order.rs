@@ -1,95 +1,114 @@
 use anchor_lang::prelude::*;
+use crate::migration::{decode, Migrate};
+use crate::state::config::*;
 use crate::state::order::*;
+use crate::state::Versioned;
 
-// Define a public function `migrate_order_new` that migrates order information from one account to another.
-pub fn migrate_order_new(ctx: Context<MigrationOrderNew>) -> Result<()> {
-    let order_before = &mut ctx.accounts.order_before;
-    let order_after = &mut ctx.accounts.order_after;
-    order_after.order_id = order_before.order_id;
-    order_after.buyer = order_before.buyer;
-    order_after.seller = order_before.seller;
-    order_after.machine_id = order_before.machine_id;
-    order_after.price = order_before.price;
-    order_after.duration = order_before.duration;
-    order_after.total = order_before.total;
-    order_after.metadata = order_before.metadata.clone();
-    order_after.status = match order_before.status {
-        OrderStatus::Preparing => OrderStatus::Training,
-        OrderStatus::Training => OrderStatus::Completed,
-        OrderStatus::Completed => OrderStatus::Failed,
-        _ => OrderStatus::Refunded,
-    };
-    order_after.order_time = order_before.order_time;
-    order_after.start_time = order_before.order_time;
-    order_after.refund_time = order_before.refund_time;
-
-    Ok(())
+/// Layout of `Order` accounts before versioning.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace)]
+pub struct OrderV0 {
+    pub order_id: [u8; 16],
+    pub buyer: Pubkey,
+    pub seller: Pubkey,
+    pub machine_id: [u8; 16],
+    pub price: u64,
+    pub duration: u32,
+    pub total: u64,
+    #[max_len(2048)]
+    pub metadata: String,
+    pub status: OrderStatus,
+    pub order_time: i64,
+    pub start_time: i64,
+    pub refund_time: i64,
 }
 
-// Define a public function `migrate_order_rename` that handles the migration of an order record
-// by renaming it in the context of a Solana program.
-pub fn migrate_order_rename(ctx: Context<MigrationOrderRename>) -> Result<()> {
-    let order_before = &mut ctx.accounts.order_before;
-    let order_after = &mut ctx.accounts.order_after;
-    order_after.order_id = order_before.order_id;
-    order_after.buyer = order_before.buyer;
-    order_after.seller = order_before.seller;
-    order_after.machine_id = order_before.machine_id;
-    order_after.price = order_before.price;
-    order_after.duration = order_before.duration;
-    order_after.total = order_before.total;
-    order_after.metadata = order_before.metadata.clone();
-    order_after.status = order_before.status.clone();
-    order_after.order_time = order_before.order_time;
-    order_after.start_time = order_before.start_time;
-    order_after.refund_time = order_before.refund_time;
-
-    Ok(())
+impl From<OrderV0> for Order {
+    fn from(order: OrderV0) -> Self {
+        Order {
+            order_id: order.order_id,
+            buyer: order.buyer,
+            seller: order.seller,
+            machine_id: order.machine_id,
+            price: order.price,
+            duration: order.duration,
+            total: order.total,
+            metadata: order.metadata,
+            status: order.status,
+            order_time: order.order_time,
+            start_time: order.start_time,
+            refund_time: order.refund_time,
+            version: 0,
+            mint: Pubkey::default(),
+            rating: 0,
+            billing_granularity: 0,
+            units: 0,
+            reserved_time: 0,
+            complete_time: 0,
+        }
+    }
 }
 
-// Define a Rust struct to represent the accounts involved in a migration order process.
-#[derive(Accounts)]
-pub struct MigrationOrderNew<'info> {
-    #[account(
-        mut,
-        close = signer
-    )]
-    pub order_before: Account<'info, Order>,
-
-    #[account(
-        init,
-        seeds = [b"order-new", order_before.buyer.as_ref(), order_before.order_id.as_ref()],
-        bump,
-        payer = signer,
-        space = 8 + OrderNew::INIT_SPACE
-    )]
-    pub order_after: Account<'info, OrderNew>,
+impl Migrate for Order {
+    fn migrate(data: &[u8], config: &Config) -> Result<Self> {
+        let mut order = decode::<Order, OrderV0>(data)?;
+        // Fields appended after the version the order was written with are decoded from
+        // leftover bytes, every one of them is reset
+        // Orders placed before multi-currency pricing are paid in the config mint
+        if order.version < 2 {
+            order.mint = config.mint;
+        }
+        if order.version < 3 {
+            order.rating = 0;
+        }
+        // Orders placed before configurable granularity are billed by whole billing periods
+        if order.version < 4 {
+            order.billing_granularity = config.billing_granularity()?;
+        }
+        // Orders placed before multi-tenant rentals take the whole single-unit machine
+        if order.version < 5 {
+            order.units = 1;
+        }
+        if order.version < 6 {
+            order.reserved_time = 0;
+        }
+        if order.version < 7 {
+            order.complete_time = 0;
+        }
+        order.version = Order::VERSION;
+        Ok(order)
+    }
 
-    #[account(mut)]
-    pub signer: Signer<'info>,
-
-    pub system_program: Program<'info, System>,
+    fn authority(&self) -> Pubkey {
+        self.buyer
+    }
 }
 
-#[derive(Accounts)]
-pub struct MigrationOrderRename<'info> {
-    #[account(
-        mut,
-        close = signer
-    )]
-    pub order_before: Account<'info, OrderNew>,
-
-    #[account(
-        init,
-        seeds = [b"order", order_before.buyer.as_ref(), order_before.order_id.as_ref()],
-        bump,
-        payer = signer,
-        space = 8 + Order::INIT_SPACE
-    )]
-    pub order_after: Account<'info, Order>,
-
-    #[account(mut)]
-    pub signer: Signer<'info>,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration::tests::migrate_legacy;
 
-    pub system_program: Program<'info, System>,
+    #[test]
+    fn migrate_v0_keeps_every_field() {
+        let before = OrderV0 {
+            order_id: [1; 16],
+            buyer: Pubkey::new_unique(),
+            seller: Pubkey::new_unique(),
+            machine_id: [2; 16],
+            price: 10_000_000_000,
+            duration: 10,
+            total: 100_000_000_000,
+            metadata: "{}".to_string(),
+            status: OrderStatus::Training,
+            order_time: 1_708_992_000,
+            start_time: 1_708_992_300,
+            refund_time: 0,
+        };
+        let config = Config {
+            mint: Pubkey::new_unique(),
+            ..Default::default()
+        };
+        let after = migrate_legacy(&before, |data| Order::migrate(data, &config));
+        assert_eq!(after.mint, config.mint);
+    }
 }
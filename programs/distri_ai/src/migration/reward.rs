@@ -0,0 +1,156 @@
+use anchor_lang::prelude::*;
+use crate::migration::{decode, Migrate};
+use crate::state::config::*;
+use crate::state::reward::*;
+use crate::state::Versioned;
+
+/// Layout of `Reward` accounts before versioning.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace)]
+pub struct RewardV0 {
+    pub period: u32,
+    pub start_time: i64,
+    pub pool: u64,
+    pub machine_num: u32,
+    pub unit_periodic_reward: u64,
+    pub task_num: u32,
+    pub unit_task_reward: u64,
+}
+
+impl From<RewardV0> for Reward {
+    fn from(reward: RewardV0) -> Self {
+        Reward {
+            period: reward.period,
+            start_time: reward.start_time,
+            pool: reward.pool,
+            machine_num: reward.machine_num,
+            unit_periodic_reward: reward.unit_periodic_reward,
+            task_num: reward.task_num,
+            unit_task_reward: reward.unit_task_reward,
+            version: 0,
+            // Past periods recorded before finalization may have been claimed already, so they
+            // stay claimable as they are and are left out of the reward pool solvency accounting.
+            // The current period is reopened by `migrate`
+            finalized: true,
+            remainder: 0,
+            unclaimed: 0,
+            total_weight: 0,
+            weighted: false,
+        }
+    }
+}
+
+impl Migrate for Reward {
+    fn migrate(data: &[u8], config: &Config) -> Result<Self> {
+        migrate_reward(data, config, Clock::get()?.unix_timestamp)
+    }
+
+    // Rewards have no owner, only the admin can migrate them
+    fn authority(&self) -> Pubkey {
+        Pubkey::default()
+    }
+}
+
+/// Upgrades a `Reward` account at `now_ts`.
+fn migrate_reward(data: &[u8], config: &Config, now_ts: i64) -> Result<Reward> {
+    let mut reward = decode::<Reward, RewardV0>(data)?;
+    // The current period still takes tasks, it is finalized once it ends like any later period
+    if reward.version == 0 && reward.period >= Reward::period_at(config, now_ts)? {
+        reward.finalized = false;
+    }
+    // The total weight of an older period is decoded from leftover bytes
+    if reward.version < 2 {
+        reward.total_weight = 0;
+    }
+    // Periods recorded since version 2 are weighted
+    if reward.version < 3 {
+        reward.weighted = reward.version >= 2;
+    }
+    reward.version = Reward::VERSION;
+    Ok(reward)
+}
+
+/// Layout of `RewardMachine` accounts before versioning.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace)]
+pub struct RewardMachineV0 {
+    pub period: u32,
+    pub owner: Pubkey,
+    pub machine_id: [u8; 16],
+    pub task_num: u32,
+    pub claimed: bool,
+}
+
+impl From<RewardMachineV0> for RewardMachine {
+    fn from(reward_machine: RewardMachineV0) -> Self {
+        RewardMachine {
+            period: reward_machine.period,
+            owner: reward_machine.owner,
+            machine_id: reward_machine.machine_id,
+            task_num: reward_machine.task_num,
+            claimed: reward_machine.claimed,
+            version: 0,
+            // Periods recorded before weighting share the pool equally, their machines have no weight
+            weight: 0,
+        }
+    }
+}
+
+impl Migrate for RewardMachine {
+    fn migrate(data: &[u8], _config: &Config) -> Result<Self> {
+        let mut reward_machine = decode::<RewardMachine, RewardMachineV0>(data)?;
+        reward_machine.version = RewardMachine::VERSION;
+        Ok(reward_machine)
+    }
+
+    fn authority(&self) -> Pubkey {
+        self.owner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration::tests::migrate_legacy;
+
+    #[test]
+    fn migrate_v0_keeps_every_field() {
+        let before = RewardV0 {
+            period: 42,
+            start_time: 1708992000,
+            pool: 65_750_000_000_000,
+            machine_num: 3,
+            unit_periodic_reward: 10_958_333_333_333,
+            task_num: 7,
+            unit_task_reward: 4_696_428_571_428,
+        };
+        let config = Config {
+            genesis_time: 0,
+            period_duration: 86400,
+            ..Default::default()
+        };
+        let after = migrate_legacy(&before, |data| migrate_reward(data, &config, 43 * 86400));
+        assert!(after.finalized);
+        // The current period is not finalized yet
+        let current = migrate_legacy(&before, |data| {
+            migrate_reward(data, &config, 42 * 86400 + 1)
+        });
+        assert!(!current.finalized);
+        // It keeps sharing its periodic pool equally
+        assert!(!current.weighted);
+    }
+
+    #[test]
+    fn migrate_reward_machine_v0_keeps_every_field() {
+        let before = RewardMachineV0 {
+            period: 42,
+            owner: Pubkey::new_unique(),
+            machine_id: [7; 16],
+            task_num: 3,
+            claimed: true,
+        };
+        let after = migrate_legacy(&before, |data| {
+            RewardMachine::migrate(data, &Config::default())
+        });
+        assert_eq!(after.authority(), before.owner);
+        assert_eq!(after.weight, 0);
+    }
+}
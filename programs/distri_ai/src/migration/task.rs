@@ -0,0 +1,60 @@
+use anchor_lang::prelude::*;
+use crate::migration::{decode, Migrate};
+use crate::state::config::*;
+use crate::state::task::*;
+use crate::state::Versioned;
+
+/// Layout of `Task` accounts before versioning.
+#[derive(AnchorSerialize, AnchorDeserialize, InitSpace)]
+pub struct TaskV0 {
+    pub uuid: [u8; 16],
+    pub period: u32,
+    pub owner: Pubkey,
+    pub machine_id: [u8; 16],
+    #[max_len(2048)]
+    pub metadata: String,
+}
+
+impl From<TaskV0> for Task {
+    fn from(task: TaskV0) -> Self {
+        Task {
+            uuid: task.uuid,
+            period: task.period,
+            owner: task.owner,
+            machine_id: task.machine_id,
+            metadata: task.metadata,
+            version: 0,
+        }
+    }
+}
+
+impl Migrate for Task {
+    fn migrate(data: &[u8], _config: &Config) -> Result<Self> {
+        let mut task = decode::<Task, TaskV0>(data)?;
+        task.version = Task::VERSION;
+        Ok(task)
+    }
+
+    fn authority(&self) -> Pubkey {
+        self.owner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration::tests::migrate_legacy;
+
+    #[test]
+    fn migrate_v0_keeps_every_field() {
+        let before = TaskV0 {
+            uuid: [1; 16],
+            period: 42,
+            owner: Pubkey::new_unique(),
+            machine_id: [2; 16],
+            metadata: "{}".to_string(),
+        };
+        let after = migrate_legacy(&before, |data| Task::migrate(data, &Config::default()));
+        assert_eq!(after.uuid, before.uuid);
+    }
+}
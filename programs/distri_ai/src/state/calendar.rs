@@ -0,0 +1,154 @@
+use anchor_lang::prelude::*;
+use crate::errors::DistriAIError;
+
+/// Calendar holds the pending reservations of a machine.
+#[account]
+#[derive(InitSpace)]
+pub struct Calendar {
+    /// The machine this calendar belongs to.
+    pub machine: Pubkey,
+    /// Reservations not activated or cancelled yet.
+    #[max_len(16)]
+    pub bookings: Vec<Booking>,
+}
+
+/// Booking reserves machine capacity units for a future time window.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub struct Booking {
+    /// The reserved order.
+    pub order: Pubkey,
+    /// The start of the reserved window.
+    pub start_time: i64,
+    /// The end of the reserved window, exclusive.
+    pub end_time: i64,
+    /// The number of capacity units reserved.
+    pub units: u32,
+}
+
+impl Calendar {
+    pub const MAX_BOOKINGS: usize = 16;
+
+    /// The number of units booked by reservations overlapping `[start_time, end_time)`.
+    pub fn booked_units(&self, start_time: i64, end_time: i64) -> u64 {
+        self.bookings
+            .iter()
+            .filter(|booking| booking.start_time < end_time && start_time < booking.end_time)
+            .map(|booking| u64::from(booking.units))
+            .sum()
+    }
+
+    /// Adds a booking if the overlapping bookings leave enough of `capacity` for it.
+    ///
+    /// Bookings whose window ended by `now` are pruned first, they can no longer be activated.
+    pub fn book(&mut self, booking: Booking, capacity: u32, now: i64) -> Result<()> {
+        self.bookings.retain(|booking| booking.end_time > now);
+        require_gt!(
+            Calendar::MAX_BOOKINGS,
+            self.bookings.len(),
+            DistriAIError::CalendarFull
+        );
+        let booked = self.booked_units(booking.start_time, booking.end_time);
+        require_gte!(
+            u64::from(capacity),
+            booked + u64::from(booking.units),
+            DistriAIError::ReservationConflict
+        );
+        self.bookings.push(booking);
+        Ok(())
+    }
+
+    /// Removes the booking of `order`.
+    pub fn remove(&mut self, order: &Pubkey) -> Result<Booking> {
+        let index = self
+            .bookings
+            .iter()
+            .position(|booking| booking.order == *order)
+            .ok_or(DistriAIError::IncorrectStatus)?;
+        Ok(self.bookings.swap_remove(index))
+    }
+
+    /// Removes the booking of a cancelled `order` whose window ends at `end_time`.
+    ///
+    /// A booking whose window ended by `now` may have been pruned already, any other booking must
+    /// be in this calendar.
+    pub fn cancel(&mut self, order: &Pubkey, end_time: i64, now: i64) -> Result<()> {
+        match self.remove(order) {
+            Err(_) if end_time <= now => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn booking(start_time: i64, end_time: i64, units: u32) -> Booking {
+        Booking {
+            order: Pubkey::new_unique(),
+            start_time,
+            end_time,
+            units,
+        }
+    }
+
+    #[test]
+    fn book_rejects_overlapping_bookings_over_capacity() {
+        let mut calendar = Calendar {
+            machine: Pubkey::new_unique(),
+            bookings: vec![],
+        };
+        calendar.book(booking(100, 200, 2), 2, 0).unwrap();
+        // Adjacent windows don't overlap
+        calendar.book(booking(200, 300, 2), 2, 0).unwrap();
+        calendar.book(booking(0, 100, 1), 2, 0).unwrap();
+        assert_eq!(
+            calendar.book(booking(199, 201, 1), 2, 0).unwrap_err(),
+            DistriAIError::ReservationConflict.into()
+        );
+        assert_eq!(calendar.booked_units(150, 250), 4);
+
+        let order = calendar.bookings[0].order;
+        calendar.remove(&order).unwrap();
+        calendar.book(booking(150, 160, 2), 2, 0).unwrap();
+    }
+
+    #[test]
+    fn book_prunes_ended_bookings() {
+        let mut calendar = Calendar {
+            machine: Pubkey::new_unique(),
+            bookings: vec![],
+        };
+        for start_time in 0..Calendar::MAX_BOOKINGS as i64 {
+            calendar
+                .book(booking(start_time, start_time + 1, 1), 1, 0)
+                .unwrap();
+        }
+        assert_eq!(
+            calendar.book(booking(100, 200, 1), 1, 0).unwrap_err(),
+            DistriAIError::CalendarFull.into()
+        );
+        // Bookings ending at `now` are pruned
+        calendar.book(booking(100, 200, 1), 1, 8).unwrap();
+        assert_eq!(calendar.bookings.len(), Calendar::MAX_BOOKINGS - 8 + 1);
+        assert_eq!(calendar.booked_units(0, 8), 0);
+    }
+
+    #[test]
+    fn cancel_requires_live_bookings() {
+        let mut calendar = Calendar {
+            machine: Pubkey::new_unique(),
+            bookings: vec![],
+        };
+        calendar.book(booking(100, 200, 1), 1, 0).unwrap();
+        let order = calendar.bookings[0].order;
+        assert_eq!(
+            calendar.cancel(&Pubkey::new_unique(), 200, 150).unwrap_err(),
+            DistriAIError::IncorrectStatus.into()
+        );
+        calendar.cancel(&order, 200, 150).unwrap();
+        assert!(calendar.bookings.is_empty());
+        // The booking of an ended window may have been pruned
+        calendar.cancel(&order, 200, 200).unwrap();
+    }
+}
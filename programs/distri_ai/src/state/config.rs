@@ -0,0 +1,97 @@
+use anchor_lang::prelude::*;
+use crate::errors::DistriAIError;
+
+#[account]
+#[derive(InitSpace, Default)]
+pub struct Config {
+    /// Admin authority allowed to update this config.
+    pub admin: Pubkey,
+    /// The token mint used for order payments and rewards.
+    pub mint: Pubkey,
+    /// Start time of reward period 0.
+    pub genesis_time: i64,
+    /// The number of seconds in a reward period.
+    pub period_duration: i64,
+    /// The number of seconds after ordering before a preparing order can be refunded.
+    pub refund_window: i64,
+    /// The number of seconds in one unit of order duration.
+    pub billing_period: i64,
+    /// The number of seconds after ordering before anyone can expire a preparing order.
+    pub start_timeout: i64,
+    /// The number of seconds after a training order ends before anyone can settle it.
+    pub settle_grace_period: i64,
+    /// The protocol fee taken from seller payouts, in basis points.
+    pub fee_bps: u16,
+    /// The owner of the token accounts receiving protocol fees.
+    pub treasury: Pubkey,
+    /// The minimum DIST stake a machine needs to be offered for rent.
+    pub min_stake: u64,
+    /// The number of seconds unstaked DIST stays slashable before it can be withdrawn.
+    pub unbonding_period: i64,
+    /// The share of a machine's stake slashed when one of its orders fails or expires, in basis points.
+    pub slash_bps: u16,
+    /// The share of a slash paid to the buyer as compensation, in basis points. The rest goes to the treasury.
+    pub slash_compensation_bps: u16,
+    /// The key allowed to resolve order disputes.
+    pub arbiter: Pubkey,
+    /// The number of seconds after its last heartbeat a machine can no longer be rented.
+    pub heartbeat_timeout: i64,
+    /// The key allowed to verify machines, besides the admin.
+    pub oracle: Pubkey,
+    /// The minimum score a verified machine needs to earn rewards, at least 1.
+    pub reward_min_score: u8,
+    /// The rewards of finalized periods not claimed yet, the reward pool must cover them.
+    pub reward_liabilities: u64,
+    /// The share of an order total paid to the seller when the order is settled by the crank,
+    /// in basis points. The rest is refunded to the buyer.
+    pub settle_seller_bps: u16,
+    /// The number of seconds after completion the buyer can dispute an order before its payment is released.
+    pub challenge_period: i64,
+    /// The number of seconds after opening a dispute before anyone can settle it by the time used,
+    /// if the arbiter has not ruled.
+    pub dispute_timeout: i64,
+    /// The share of a reservation total paid to the seller, less the protocol fee, when the buyer
+    /// cancels it before its window starts, in basis points.
+    pub cancel_fee_bps: u16,
+}
+
+impl Config {
+    pub const BPS_DENOMINATOR: u64 = 10000;
+
+    /// The share of `amount` in basis points, `bps` never exceeds `BPS_DENOMINATOR` once validated.
+    pub fn bps_of(amount: u64, bps: u16) -> u64 {
+        // The share never exceeds `amount`, so it fits in u64
+        let share = u128::from(amount) * u128::from(bps) / u128::from(Config::BPS_DENOMINATOR);
+        share as u64
+    }
+
+    /// The billing period as the coarsest billing granularity.
+    pub fn billing_granularity(&self) -> Result<u32> {
+        u32::try_from(self.billing_period).map_err(|_| error!(DistriAIError::InvalidConfig))
+    }
+}
+
+/// ConfigParams holds the adjustable parameters of the config.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ConfigParams {
+    pub genesis_time: i64,
+    pub period_duration: i64,
+    pub refund_window: i64,
+    pub billing_period: i64,
+    pub start_timeout: i64,
+    pub settle_grace_period: i64,
+    pub fee_bps: u16,
+    pub treasury: Pubkey,
+    pub min_stake: u64,
+    pub unbonding_period: i64,
+    pub slash_bps: u16,
+    pub slash_compensation_bps: u16,
+    pub arbiter: Pubkey,
+    pub heartbeat_timeout: i64,
+    pub oracle: Pubkey,
+    pub reward_min_score: u8,
+    pub settle_seller_bps: u16,
+    pub challenge_period: i64,
+    pub dispute_timeout: i64,
+    pub cancel_fee_bps: u16,
+}
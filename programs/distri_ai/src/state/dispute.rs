@@ -0,0 +1,26 @@
+use anchor_lang::prelude::*;
+use crate::state::order::OrderStatus;
+
+/// Dispute freezes the funds of a contested order until the arbiter resolves it.
+#[account]
+#[derive(InitSpace)]
+pub struct Dispute {
+    /// The disputed order.
+    pub order: Pubkey,
+    /// The party who opened this dispute, receives the rent when it is resolved.
+    pub opener: Pubkey,
+    /// The status of the order before it was disputed.
+    pub order_status: OrderStatus,
+    /// The evidence URI submitted by the buyer.
+    #[max_len(256)]
+    pub buyer_evidence: String,
+    /// The evidence URI submitted by the seller.
+    #[max_len(256)]
+    pub seller_evidence: String,
+    /// The time this dispute was opened.
+    pub open_time: i64,
+}
+
+impl Dispute {
+    pub const EVIDENCE_MAX_LENGTH: usize = 256;
+}
@@ -1,7 +1,10 @@
 use anchor_lang::prelude::*;
+use crate::errors::DistriAIError;
+use crate::state::config::Config;
+use crate::state::Versioned;
 
 #[account]
-#[derive(InitSpace)]
+#[derive(InitSpace, Default)]
 pub struct Machine {
     /// Owner of this machine.
     pub owner: Pubkey,
@@ -12,7 +15,7 @@ pub struct Machine {
     pub metadata: String,
     /// The status of this machine.
     pub status: MachineStatus,
-    /// The price of this machine.
+    /// The price of this machine per hour, in units of `mint`.
     pub price: u64,
     /// The maximum number of hours the machine can be rent.
     pub max_duration: u32,
@@ -22,7 +25,7 @@ pub struct Machine {
     pub completed_count: u32,
     /// The total number of failed orders for this machine.
     pub failed_count: u32,
-    /// Computing power score of this machine.
+    /// Computing power score of this machine, set by the admin or the oracle.
     pub score: u8,
     /// Total claimed periodic rewards.
     pub claimed_periodic_rewards: u64,
@@ -30,49 +33,233 @@ pub struct Machine {
     pub claimed_task_rewards: u64,
     // Rencently order pda
     pub order_pda: Pubkey,
+    /// The layout version of this account.
+    pub version: u8,
+    /// The token mint the price of this machine is paid in.
+    pub mint: Pubkey,
+    /// The amount of DIST bonded by the owner, slashable while the machine is offered.
+    pub stake: u64,
+    /// The amount of DIST waiting to be withdrawn after unbonding.
+    pub unbonding_stake: u64,
+    /// The time after which the unbonding stake can be withdrawn.
+    pub unbonding_time: i64,
+    /// The sum of all ratings given by buyers.
+    pub rating_sum: u64,
+    /// The number of ratings given by buyers.
+    pub rating_count: u32,
+    /// The number of seconds orders of this machine are billed by, divides the config billing period.
+    pub billing_granularity: u32,
+    /// The offer terms replacing the current ones once effective.
+    ///
+    /// They are applied by the next order, offer update or heartbeat, so until then clients must
+    /// quote this offer instead of `price`, `max_duration` and `disk` once it is effective.
+    pub pending_offer: Option<PendingOffer>,
+    /// The structured hardware spec of this machine, alongside the free-form `metadata`.
+    pub spec: MachineSpec,
+    /// The number of capacity units, such as GPUs, offered for rent.
+    pub capacity: u32,
+    /// The number of capacity units reserved by active orders.
+    pub allocated: u32,
+    /// The last time the owner attested this machine is online.
+    pub last_seen: i64,
+    /// Whether the admin or the oracle verified this machine is a distinct, real machine.
+    pub verified: bool,
+    /// The number of placed or reserved orders whose escrow is not paid out yet.
+    pub open_orders: u32,
 }
 
 impl Machine {
     pub const METADATA_MAX_LENGTH: usize = 2048;
+
+    /// Reserves `units` of free capacity for an order, the machine is `Renting` once fully allocated.
+    pub fn allocate(&mut self, units: u32) -> Result<()> {
+        require!(
+            self.status == MachineStatus::ForRent,
+            DistriAIError::IncorrectStatus
+        );
+        require_gt!(units, 0, DistriAIError::InsufficientCapacity);
+        let free = self.capacity.saturating_sub(self.allocated);
+        require_gte!(free, units, DistriAIError::InsufficientCapacity);
+
+        self.allocated += units;
+        if self.allocated == self.capacity {
+            self.status = MachineStatus::Renting;
+        }
+        Ok(())
+    }
+
+    /// Releases the `units` reserved by a finished order, a fully allocated machine is `ForRent` again.
+    pub fn release(&mut self, units: u32) -> Result<()> {
+        self.allocated = self
+            .allocated
+            .checked_sub(units)
+            .ok_or(DistriAIError::IncorrectStatus)?;
+        if self.status == MachineStatus::Renting {
+            self.status = MachineStatus::ForRent;
+        }
+        Ok(())
+    }
+
+    /// Counts an order placed or reserved on this machine, it can't be removed until the order is closed.
+    pub fn open_order(&mut self) -> Result<()> {
+        self.open_orders = self
+            .open_orders
+            .checked_add(1)
+            .ok_or(DistriAIError::MathOverflow)?;
+        Ok(())
+    }
+
+    /// Counts an order as closed once its escrow is paid out.
+    pub fn close_order(&mut self) {
+        // Orders placed before open orders were counted are not included
+        self.open_orders = self.open_orders.saturating_sub(1);
+    }
+
+    /// Whether the machine has sent a heartbeat within `heartbeat_timeout` seconds of `now_ts`.
+    pub fn is_online(&self, now_ts: i64, heartbeat_timeout: i64) -> bool {
+        now_ts.saturating_sub(self.last_seen) <= heartbeat_timeout
+    }
+
+    /// Whether this machine can earn periodic and task rewards.
+    pub fn is_reward_eligible(&self, config: &Config) -> bool {
+        self.verified && self.stake >= config.min_stake && self.score >= config.reward_min_score
+    }
+
+    /// Applies the pending offer once it is effective at `now_ts`.
+    pub fn apply_pending_offer(&mut self, now_ts: i64) {
+        let effective = self
+            .pending_offer
+            .as_ref()
+            .is_some_and(|offer| offer.effective_time <= now_ts);
+        if !effective {
+            return;
+        }
+        if let Some(offer) = self.pending_offer.take() {
+            self.price = offer.price;
+            self.max_duration = offer.max_duration;
+            self.disk = offer.disk;
+        }
+    }
+
+    /// Deducts `slash_bps` of the bonded and unbonding stake, returning the slashed amount.
+    ///
+    /// Unbonding stake is slashable too, so misbehaving owners can't escape by unbonding first.
+    pub fn slash(&mut self, slash_bps: u16) -> Result<u64> {
+        let slashable = u128::from(self.stake) + u128::from(self.unbonding_stake);
+        let amount = slashable * u128::from(slash_bps) / u128::from(Config::BPS_DENOMINATOR);
+        let amount = u64::try_from(amount).map_err(|_| DistriAIError::MathOverflow)?;
+
+        let from_stake = amount.min(self.stake);
+        self.stake -= from_stake;
+        self.unbonding_stake = self
+            .unbonding_stake
+            .checked_sub(amount - from_stake)
+            .ok_or(DistriAIError::MathOverflow)?;
+        Ok(amount)
+    }
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct MachineNew {
-    pub owner: Pubkey,  // 32
-    pub uuid: [u8; 16], // 16
-    /// The metadata by json format of this machine.
-    #[max_len(2048)]
-    pub metadata: String, // 4 + 2048
-    /// The status of this machine.
-    pub status: MachineStatus, // 1 + 1
-    /// The price of this machine.
-    pub price: u64, // 8
-    /// The maximum number of hours the machine can be rent.
-    pub max_duration: u32, // 4
-    /// The GB amount of this machine's avaliable disk.
-    pub disk: u32, // 4
+impl Versioned for Machine {
+    // 1: version, 2: mint, 3: stake, 4: ratings, 5: billing_granularity, 6: pending_offer,
+    // 7: spec, 8: capacity and allocated, 9: last_seen, 10: verified, 11: open_orders
+    const VERSION: u8 = 11;
 
-    pub completed_count: u32, // 4
+    fn version(&self) -> u8 {
+        self.version
+    }
+}
 
-    pub failed_count: u32, // 4
+/// MachineSpec holds the hardware spec of a machine.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace, Default)]
+pub struct MachineSpec {
+    /// The number of GPUs.
+    pub gpu_count: u16,
+    /// The GB amount of VRAM per GPU.
+    pub vram: u32,
+    /// The number of CPU cores.
+    pub cpu_cores: u16,
+    /// The GB amount of RAM.
+    pub ram: u32,
+    /// The ISO 3166-1 alpha-2 code of the region the machine is located in.
+    pub region: [u8; 2],
+}
 
-    pub score: u8, // 1
-    /// Total claimed periodic rewards.
-    pub claimed_periodic_rewards: u64, // 8
-    /// Total claimed task rewards.
-    pub claimed_task_rewards: u64, // 8
-    // Rencently order pda
-    pub order_pda: Pubkey,
+/// PendingOffer holds offer terms scheduled to replace the current ones of a machine.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+pub struct PendingOffer {
+    /// The new price per hour, in units of the machine `mint`.
+    pub price: u64,
+    /// The new maximum number of hours the machine can be rent.
+    pub max_duration: u32,
+    /// The new GB amount of avaliable disk.
+    pub disk: u32,
+    /// The time the new terms take effect.
+    pub effective_time: i64,
 }
 
 /// MachineStatus holds the current state of the machine.
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace, Default)]
 pub enum MachineStatus {
     /// This machine is idle, not display in the market.
+    #[default]
     Idle,
-    /// This machine is for rent, display in the market.
+    /// This machine has free capacity for rent, display in the market.
     ForRent,
-    /// This machine is on lease, not display in the market.
+    /// All capacity of this machine is on lease, not display in the market.
     Renting,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(stake: u64, unbonding_stake: u64) -> Machine {
+        Machine {
+            stake,
+            unbonding_stake,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn allocate_and_release_capacity() {
+        let mut m = Machine {
+            status: MachineStatus::ForRent,
+            capacity: 8,
+            ..Default::default()
+        };
+        m.allocate(3).unwrap();
+        assert!(m.status == MachineStatus::ForRent);
+        assert_eq!(
+            m.allocate(6).unwrap_err(),
+            DistriAIError::InsufficientCapacity.into()
+        );
+        m.allocate(5).unwrap();
+        assert_eq!(m.allocated, 8);
+        assert!(m.status == MachineStatus::Renting);
+        assert_eq!(m.allocate(1).unwrap_err(), DistriAIError::IncorrectStatus.into());
+
+        m.release(5).unwrap();
+        assert_eq!(m.allocated, 3);
+        assert!(m.status == MachineStatus::ForRent);
+        assert_eq!(m.release(4).unwrap_err(), DistriAIError::IncorrectStatus.into());
+    }
+
+    #[test]
+    fn slash_takes_stake_before_unbonding_stake() {
+        let mut m = machine(100, 100);
+        assert_eq!(m.slash(7500).unwrap(), 150);
+        assert_eq!((m.stake, m.unbonding_stake), (0, 50));
+
+        let mut m = machine(u64::MAX, 0);
+        assert_eq!(m.slash(10000).unwrap(), u64::MAX);
+        assert_eq!(m.stake, 0);
+
+        let mut m = machine(u64::MAX, u64::MAX);
+        assert_eq!(m.slash(0).unwrap(), 0);
+        assert_eq!(
+            m.slash(10000).unwrap_err(),
+            DistriAIError::MathOverflow.into()
+        );
+    }
+}
@@ -1,13 +1,34 @@
 pub use ai_model::*;
+pub use calendar::*;
+pub use config::*;
 pub use dataset::*;
+pub use dispute::*;
 pub use machine::*;
 pub use order::*;
+pub use payment_mint::*;
 pub use reward::*;
 pub use task::*;
 
 pub mod ai_model;
+pub mod calendar;
+pub mod config;
 pub mod dataset;
+pub mod dispute;
 pub mod machine;
 pub mod order;
+pub mod payment_mint;
 pub mod reward;
 pub mod task;
+
+/// An account with a versioned data layout.
+///
+/// When the account struct changes, append the new fields, bump `VERSION` and fill the new
+/// fields of accounts older than that version in their migration, instead of adding a temporary
+/// copy of the struct.
+pub trait Versioned {
+    /// The current layout version.
+    const VERSION: u8;
+
+    /// The layout version this account was written with.
+    fn version(&self) -> u8;
+}
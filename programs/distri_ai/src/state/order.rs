@@ -1,7 +1,9 @@
 use anchor_lang::prelude::*;
+use crate::errors::DistriAIError;
+use crate::state::Versioned;
 
 #[account]
-#[derive(InitSpace)]
+#[derive(InitSpace, Default)]
 pub struct Order {
     /// UUID of this order.
     pub order_id: [u8; 16],
@@ -28,45 +30,104 @@ pub struct Order {
     pub start_time: i64,
     /// The refund time of this order.
     pub refund_time: i64,
+    /// The layout version of this account.
+    pub version: u8,
+    /// The token mint this order is paid in.
+    pub mint: Pubkey,
+    /// The rating given by the buyer, 0 if not rated yet.
+    pub rating: u8,
+    /// The number of seconds this order is billed by, copied from the machine when placed.
+    pub billing_granularity: u32,
+    /// The number of machine capacity units reserved by this order.
+    pub units: u32,
+    /// The scheduled start time of a reserved order, 0 if placed for immediate use.
+    pub reserved_time: i64,
+    /// The time the seller completed this order, 0 if not completed yet.
+    pub complete_time: i64,
 }
 
 impl Order {
     pub const METADATA_MAX_LENGTH: usize = 2048;
+    pub const MIN_RATING: u8 = 1;
+    pub const MAX_RATING: u8 = 5;
+
+    /// The number of seconds in `duration` billing periods.
+    pub fn duration_seconds(duration: u32, billing_period: i64) -> Result<i64> {
+        billing_period
+            .checked_mul(duration.into())
+            .ok_or(error!(DistriAIError::MathOverflow))
+    }
+
+    /// The time the paid duration of this order ends.
+    pub fn end_time(&self, billing_period: i64) -> Result<i64> {
+        self.start_time
+            .checked_add(Order::duration_seconds(self.duration, billing_period)?)
+            .ok_or(error!(DistriAIError::MathOverflow))
+    }
+
+    /// The time the payment of a delivered order can be released, unless disputed before.
+    pub fn challenge_end_time(&self, challenge_period: i64) -> Result<i64> {
+        self.complete_time
+            .checked_add(challenge_period)
+            .ok_or(error!(DistriAIError::MathOverflow))
+    }
+
+    /// The amount charged for renewing this order by `duration` billing periods,
+    /// locked to the price the order was placed at.
+    pub fn renewal_amount(&self, duration: u32, billing_period: i64) -> Result<u64> {
+        Order::billed_amount(
+            self.price,
+            self.units,
+            self.billing_granularity,
+            billing_period,
+            Order::duration_seconds(duration, billing_period)?,
+        )
+    }
+
+    /// The amount charged for `seconds` of use of `units` at `price` per unit and `billing_period`,
+    /// with the used time rounded up to whole `billing_granularity` units.
+    pub fn billed_amount(
+        price: u64,
+        units: u32,
+        billing_granularity: u32,
+        billing_period: i64,
+        seconds: i64,
+    ) -> Result<u64> {
+        let granularity = u128::from(billing_granularity);
+        let billing_period =
+            u128::try_from(billing_period).map_err(|_| DistriAIError::InvalidConfig)?;
+        let seconds = u128::try_from(seconds).map_err(|_| DistriAIError::InvalidDuration)?;
+        require!(
+            granularity > 0 && billing_period > 0,
+            DistriAIError::InvalidBillingGranularity
+        );
+
+        let billed_seconds = seconds.div_ceil(granularity);
+        let amount = u128::from(price)
+            .checked_mul(u128::from(units))
+            .and_then(|amount| amount.checked_mul(billed_seconds))
+            .and_then(|amount| amount.checked_mul(granularity))
+            .ok_or(DistriAIError::MathOverflow)?
+            / billing_period;
+        u64::try_from(amount).map_err(|_| error!(DistriAIError::MathOverflow))
+    }
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct OrderNew {
-    pub order_id: [u8; 16], // 16
-    /// The buyer of this order.
-    pub buyer: Pubkey, // 32
-    /// The seller of this order.
-    pub seller: Pubkey, // 32
-    /// The machine id of this order.
-    pub machine_id: [u8; 16], // 16
-    /// The price of this order.
-    pub price: u64, //8
-    /// The duration hours of this order.
-    pub duration: u32, // 4
-    /// The total amount of this order.
-    pub total: u64, //8
-    /// The metadata by json format of this order.
-    #[max_len(2048)]
-    pub metadata: String, // 4 + 2048
-    /// The status of this order.
-    pub status: OrderStatus, // 1 + 1
-    /// The order time of this order.
-    pub order_time: i64, // 8
-    /// The start time of this order.
-    pub start_time: i64, // 8
-    /// The refund time of this order.
-    pub refund_time: i64, // 8
+impl Versioned for Order {
+    // 1: version, 2: mint, 3: rating, 4: billing_granularity, 5: units, 6: reserved_time,
+    // 7: complete_time
+    const VERSION: u8 = 7;
+
+    fn version(&self) -> u8 {
+        self.version
+    }
 }
 
 /// OrderStatus holds the current state of the order.
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace)]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, InitSpace, Default)]
 pub enum OrderStatus {
     /// This order is preparing. The state of the machine is `Renting`.
+    #[default]
     Preparing,
     /// This order is in training. The state of the machine is `Renting`.
     Training,
@@ -76,4 +137,92 @@ pub enum OrderStatus {
     Failed,
     /// This order was refunded.
     Refunded,
+    /// This order is disputed, its funds are frozen until the arbiter resolves it.
+    Disputed,
+    /// This order is reserved for a future window and waits for activation.
+    Reserved,
+    /// This order was completed by the seller, its payment is held until the challenge period ends.
+    Delivered,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 3600;
+
+    #[test]
+    fn billed_amount_rounds_up_to_granularity() {
+        // Per-second, per-minute and per-hour billing of 90 seconds at 3600 per hour
+        assert_eq!(Order::billed_amount(3600, 1, 1, HOUR, 90).unwrap(), 90);
+        assert_eq!(Order::billed_amount(3600, 1, 60, HOUR, 90).unwrap(), 120);
+        assert_eq!(Order::billed_amount(3600, 1, 3600, HOUR, 90).unwrap(), 3600);
+        assert_eq!(Order::billed_amount(3600, 1, 60, HOUR, 0).unwrap(), 0);
+        // Whole periods are billed exactly at any granularity
+        assert_eq!(Order::billed_amount(7, 1, 60, HOUR, 5 * HOUR).unwrap(), 35);
+        // Every unit is billed at the unit price
+        assert_eq!(Order::billed_amount(7, 4, 60, HOUR, 5 * HOUR).unwrap(), 140);
+    }
+
+    #[test]
+    fn billed_amount_boundaries() {
+        assert_eq!(
+            Order::billed_amount(u64::MAX, 1, 3600, HOUR, HOUR).unwrap(),
+            u64::MAX
+        );
+        assert_eq!(
+            Order::billed_amount(u64::MAX, 1, 3600, HOUR, HOUR + 1).unwrap_err(),
+            DistriAIError::MathOverflow.into()
+        );
+        assert_eq!(
+            Order::billed_amount(u64::MAX, 2, 3600, HOUR, HOUR).unwrap_err(),
+            DistriAIError::MathOverflow.into()
+        );
+        assert_eq!(
+            Order::billed_amount(1, 1, 60, HOUR, -1).unwrap_err(),
+            DistriAIError::InvalidDuration.into()
+        );
+        assert_eq!(
+            Order::billed_amount(1, 1, 0, HOUR, 60).unwrap_err(),
+            DistriAIError::InvalidBillingGranularity.into()
+        );
+    }
+
+    #[test]
+    fn renewal_bills_order_terms() {
+        // The order was placed at 10 per hour for 2 units, billed by the minute
+        let order = Order {
+            price: 10,
+            units: 2,
+            billing_granularity: 60,
+            duration: 2,
+            ..Default::default()
+        };
+        assert_eq!(order.renewal_amount(3, HOUR).unwrap(), 60);
+        assert_eq!(
+            order.renewal_amount(u32::MAX, i64::MAX).unwrap_err(),
+            DistriAIError::MathOverflow.into()
+        );
+    }
+
+    #[test]
+    fn end_time_boundaries() {
+        assert_eq!(
+            Order::duration_seconds(u32::MAX, HOUR).unwrap(),
+            i64::from(u32::MAX) * HOUR
+        );
+        assert_eq!(
+            Order::duration_seconds(2, i64::MAX).unwrap_err(),
+            DistriAIError::MathOverflow.into()
+        );
+
+        let mut order = Order {
+            start_time: 1_000,
+            duration: 2,
+            ..Default::default()
+        };
+        assert_eq!(order.end_time(HOUR).unwrap(), 1_000 + 2 * HOUR);
+        order.start_time = i64::MAX - HOUR;
+        assert_eq!(order.end_time(HOUR).unwrap_err(), DistriAIError::MathOverflow.into());
+    }
 }
@@ -0,0 +1,9 @@
+use anchor_lang::prelude::*;
+
+/// PaymentMint marks a token mint the admin allows machine offers to be priced in.
+#[account]
+#[derive(InitSpace)]
+pub struct PaymentMint {
+    /// The allowed token mint.
+    pub mint: Pubkey,
+}
@@ -1,7 +1,10 @@
 use anchor_lang::prelude::*;
+use crate::errors::DistriAIError;
+use crate::state::config::*;
+use crate::state::Versioned;
 
 #[account]
-#[derive(InitSpace)]
+#[derive(InitSpace, Default)]
 pub struct Reward {
     /// Reward period.
     pub period: u32,
@@ -11,21 +14,34 @@ pub struct Reward {
     pub pool: u64,
     /// Participating machine number in this period.
     pub machine_num: u32,
-    /// Periodic reward per machine in this period.
+    /// Periodic reward per machine of periods recorded before weighting, 0 otherwise.
     pub unit_periodic_reward: u64,
     /// Task number in this period.
     pub task_num: u32,
     /// Task reward per task in this period.
     pub unit_task_reward: u64,
+    /// The layout version of this account.
+    pub version: u8,
+    /// Whether the unit rewards are locked and can be claimed.
+    pub finalized: bool,
+    /// The part of the pool left undistributed by rounding.
+    pub remainder: u64,
+    /// The finalized rewards of this period not claimed yet.
+    pub unclaimed: u64,
+    /// The sum of the weights of all participating machines in this period.
+    pub total_weight: u64,
+    /// Whether the periodic pool is shared by machine weight, periods recorded before weighting
+    /// share it equally.
+    pub weighted: bool,
 }
 
 impl Reward {
-    /// Period 0 start time: 2024-02-27 00:00:00 UTC
-    const GENESIS_TIME: i64 = 1708992000;
-    const PERIOD_DURATION: i64 = 86400;
     const DECAY_PERIODS: u32 = 4;
     const DECAY_RATE_NUMERATOR: u64 = 9737;
     const DECAY_RATE_DENOMINATOR: u64 = 10000;
+    /// Share of the period pool allocated to task rewards, the rest goes to periodic rewards.
+    const TASK_POOL_NUMERATOR: u64 = 5000;
+    const TASK_POOL_DENOMINATOR: u64 = 10000;
     // const GENESIS_POOL: u64 = 65_750_000_000_000;
     /// Checkpoint every 10 decays
     const POOL_CHECKPOINTS: [u64; 31] = [
@@ -63,55 +79,116 @@ impl Reward {
     ];
     // current_period calculates the current reward period based on the current Unix timestamp.
     // It returns a Result with a u32 representing the current period or an error if the operation fails.
-    pub fn current_period() -> Result<u32> {
+    pub fn current_period(config: &Config) -> Result<u32> {
         let now_ts = Clock::get()?.unix_timestamp;
-        let period: u32 = now_ts
-            .saturating_sub(Reward::GENESIS_TIME)
-            .saturating_div(Reward::PERIOD_DURATION)
-            .try_into()
-            .unwrap();
-        Ok(period)
+        Reward::period_at(config, now_ts)
     }
+
+    /// The reward period containing the timestamp `ts`.
+    pub fn period_at(config: &Config, ts: i64) -> Result<u32> {
+        let elapsed = ts
+            .checked_sub(config.genesis_time)
+            .ok_or(DistriAIError::MathOverflow)?;
+        require_gte!(elapsed, 0, DistriAIError::InvalidPeriod);
+        let period = elapsed
+            .checked_div(config.period_duration)
+            .ok_or(DistriAIError::InvalidConfig)?;
+        u32::try_from(period).map_err(|_| error!(DistriAIError::MathOverflow))
+    }
+
     /// Calculates the pool value after applying decay for a given period.
-    pub fn pool(period: u32) -> u64 {
+    pub fn pool(period: u32) -> Result<u64> {
         // Calculate how many full DECAY_PERIODS fit into the given period
-        let decay_times: usize = period
-            .saturating_div(Reward::DECAY_PERIODS)
-            .try_into()
-            .unwrap();
-        
-        // Determine the checkpoint index based on the decay times
-        let mut checkpoint_index: usize = decay_times.saturating_div(10).try_into().unwrap();
-        
-        // Ensure checkpoint_index does not exceed the length of POOL_CHECKPOINTS
-        if checkpoint_index > Reward::POOL_CHECKPOINTS.len() - 1 {
-            checkpoint_index = Reward::POOL_CHECKPOINTS.len() - 1;
-        }
-        
+        let decay_times = usize::try_from(period / Reward::DECAY_PERIODS)
+            .map_err(|_| DistriAIError::MathOverflow)?;
+
+        // Determine the checkpoint index based on the decay times,
+        // not exceeding the length of POOL_CHECKPOINTS
+        let checkpoint_index = (decay_times / 10).min(Reward::POOL_CHECKPOINTS.len() - 1);
+
         // Calculate remaining decay times after considering the checkpoint index
-        let remaining_decay_times = decay_times.saturating_sub(checkpoint_index.saturating_mul(10));
-        
+        let remaining_decay_times = decay_times - checkpoint_index * 10;
+
         // Initialize the pool with the value from POOL_CHECKPOINTS at checkpoint_index
         let mut pool = Reward::POOL_CHECKPOINTS[checkpoint_index];
-        
-        // Apply decay for the remaining decay times
+
+        // Apply decay for the remaining decay times, until the pool runs out
         for _ in 0..remaining_decay_times {
+            if pool == 0 {
+                break;
+            }
             pool = pool
-                .saturating_mul(Reward::DECAY_RATE_NUMERATOR)
-                .saturating_div(Reward::DECAY_RATE_DENOMINATOR);
+                .checked_mul(Reward::DECAY_RATE_NUMERATOR)
+                .ok_or(DistriAIError::MathOverflow)?
+                / Reward::DECAY_RATE_DENOMINATOR;
         }
-        pool
+        Ok(pool)
+    }
+
+    /// Part of the pool shared by all tasks submitted in the period.
+    pub fn task_pool(pool: u64) -> u64 {
+        // The task pool never exceeds `pool`, so it fits in u64
+        let task_pool = u128::from(pool) * u128::from(Reward::TASK_POOL_NUMERATOR)
+            / u128::from(Reward::TASK_POOL_DENOMINATOR);
+        task_pool as u64
     }
 
-    pub fn start_time(period: u32) -> i64 {
-        Reward::PERIOD_DURATION
-            .saturating_mul(period.into())
-            .saturating_add(Reward::GENESIS_TIME)
+    /// Part of the pool shared by all machines participating in the period.
+    pub fn periodic_pool(pool: u64) -> u64 {
+        pool - Reward::task_pool(pool)
+    }
+
+    /// The periodic reward of a machine with `weight` in this period.
+    ///
+    /// Periods recorded before weighting share the pool equally.
+    pub fn periodic_reward(&self, weight: u64) -> Result<u64> {
+        if !self.weighted {
+            return Ok(self.unit_periodic_reward);
+        }
+        let reward = u128::from(Reward::periodic_pool(self.pool))
+            .checked_mul(u128::from(weight))
+            .and_then(|reward| reward.checked_div(u128::from(self.total_weight)))
+            .ok_or(DistriAIError::MathOverflow)?;
+        u64::try_from(reward).map_err(|_| error!(DistriAIError::MathOverflow))
+    }
+
+    /// The total rewards distributed to machines in this period.
+    ///
+    /// Weighted shares are rounded down, so the periodic pool is an upper bound of their sum.
+    pub fn distributed(&self) -> Result<u64> {
+        let periodic_rewards = if !self.weighted {
+            self.unit_periodic_reward
+                .checked_mul(self.machine_num.into())
+        } else {
+            Some(Reward::periodic_pool(self.pool))
+        };
+        let task_rewards = self.unit_task_reward.checked_mul(self.task_num.into());
+        periodic_rewards
+            .zip(task_rewards)
+            .and_then(|(periodic_rewards, task_rewards)| periodic_rewards.checked_add(task_rewards))
+            .ok_or(error!(DistriAIError::MathOverflow))
+    }
+
+    pub fn start_time(config: &Config, period: u32) -> Result<i64> {
+        config
+            .period_duration
+            .checked_mul(period.into())
+            .and_then(|offset| offset.checked_add(config.genesis_time))
+            .ok_or(error!(DistriAIError::MathOverflow))
+    }
+}
+
+impl Versioned for Reward {
+    // 1: version, finalized, remainder and unclaimed, 2: total_weight, 3: weighted
+    const VERSION: u8 = 3;
+
+    fn version(&self) -> u8 {
+        self.version
     }
 }
 
 #[account]
-#[derive(InitSpace)]
+#[derive(InitSpace, Default)]
 pub struct RewardMachine {
     pub period: u32,
     /// Machine owner.
@@ -122,4 +199,162 @@ pub struct RewardMachine {
     pub task_num: u32,
     /// Reward has been claimed.
     pub claimed: bool,
+    /// The layout version of this account.
+    pub version: u8,
+    /// The share weight of this machine in the periodic rewards.
+    pub weight: u64,
+}
+
+impl RewardMachine {
+    /// The uptime factor of a machine without completed orders.
+    const UPTIME_BASE: u64 = 10;
+    /// The number of completed orders at which the uptime factor stops growing.
+    const UPTIME_MAX_ORDERS: u32 = 10;
+
+    /// The periodic reward weight of a machine, the product of its computing power score,
+    /// its tasks submitted in the period and an uptime factor growing with its completed orders
+    /// up to twice the base.
+    pub fn weight(score: u8, task_num: u32, completed_count: u32) -> Result<u64> {
+        let uptime = RewardMachine::UPTIME_BASE
+            + u64::from(completed_count.min(RewardMachine::UPTIME_MAX_ORDERS));
+        u64::from(score)
+            .checked_mul(task_num.into())
+            .and_then(|weight| weight.checked_mul(uptime))
+            .ok_or(error!(DistriAIError::MathOverflow))
+    }
+}
+
+impl Versioned for RewardMachine {
+    const VERSION: u8 = 1;
+
+    fn version(&self) -> u8 {
+        self.version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            genesis_time: 1_000,
+            period_duration: 86400,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn period_at_boundaries() {
+        let config = config();
+        assert_eq!(Reward::period_at(&config, 1_000).unwrap(), 0);
+        assert_eq!(Reward::period_at(&config, 1_000 + 86399).unwrap(), 0);
+        assert_eq!(Reward::period_at(&config, 1_000 + 86400).unwrap(), 1);
+        assert_eq!(
+            Reward::period_at(&config, 999).unwrap_err(),
+            DistriAIError::InvalidPeriod.into()
+        );
+        assert_eq!(
+            Reward::period_at(&config, i64::MIN).unwrap_err(),
+            DistriAIError::MathOverflow.into()
+        );
+
+        let config = Config {
+            period_duration: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            Reward::period_at(&config, i64::from(u32::MAX)).unwrap(),
+            u32::MAX
+        );
+        assert_eq!(
+            Reward::period_at(&config, i64::from(u32::MAX) + 1).unwrap_err(),
+            DistriAIError::MathOverflow.into()
+        );
+    }
+
+    #[test]
+    fn start_time_boundaries() {
+        let config = config();
+        assert_eq!(Reward::start_time(&config, 0).unwrap(), 1_000);
+        assert_eq!(Reward::start_time(&config, 2).unwrap(), 1_000 + 2 * 86400);
+
+        let config = Config {
+            genesis_time: i64::MAX,
+            period_duration: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            Reward::start_time(&config, 1).unwrap_err(),
+            DistriAIError::MathOverflow.into()
+        );
+    }
+
+    #[test]
+    fn pool_splits_and_decays() {
+        assert_eq!(Reward::pool(0).unwrap(), Reward::POOL_CHECKPOINTS[0]);
+        assert_eq!(Reward::pool(u32::MAX).unwrap(), 0);
+        assert_eq!(Reward::task_pool(u64::MAX), u64::MAX / 2);
+        assert_eq!(Reward::periodic_pool(u64::MAX), u64::MAX / 2 + 1);
+        assert_eq!(Reward::task_pool(1) + Reward::periodic_pool(1), 1);
+    }
+
+    #[test]
+    fn distributed_leaves_rounding_remainder() {
+        let pool = 1_000_003;
+        let reward = Reward {
+            pool,
+            machine_num: 3,
+            unit_periodic_reward: Reward::periodic_pool(pool) / 3,
+            task_num: 7,
+            unit_task_reward: Reward::task_pool(pool) / 7,
+            ..Default::default()
+        };
+        let distributed = reward.distributed().unwrap();
+        assert_eq!(distributed, 166_667 * 3 + 71_428 * 7);
+        assert!(pool - distributed < 3 + 7);
+
+        let reward = Reward {
+            machine_num: 2,
+            unit_periodic_reward: u64::MAX,
+            ..Default::default()
+        };
+        assert_eq!(reward.distributed().unwrap_err(), DistriAIError::MathOverflow.into());
+    }
+
+    #[test]
+    fn weighted_periodic_rewards() {
+        // A strong busy machine with a full uptime and a weak idle one
+        let strong = RewardMachine::weight(80, 6, 25).unwrap();
+        let weak = RewardMachine::weight(20, 1, 0).unwrap();
+        assert_eq!(strong, 80 * 6 * 20);
+        assert_eq!(weak, 20 * 10);
+        assert_eq!(RewardMachine::weight(0, 6, 25).unwrap(), 0);
+
+        let pool = 1_000_003;
+        let reward = Reward {
+            pool,
+            machine_num: 2,
+            task_num: 7,
+            unit_task_reward: Reward::task_pool(pool) / 7,
+            total_weight: strong + weak,
+            weighted: true,
+            ..Default::default()
+        };
+        let strong_reward = reward.periodic_reward(strong).unwrap();
+        let weak_reward = reward.periodic_reward(weak).unwrap();
+        assert_eq!(strong_reward, Reward::periodic_pool(pool) * strong / (strong + weak));
+        assert!(strong_reward > weak_reward * 40);
+        // Shares never exceed what is reserved for the period
+        assert!(strong_reward + weak_reward + 71_428 * 7 <= reward.distributed().unwrap());
+        assert_eq!(reward.periodic_reward(u64::MAX).unwrap_err(), DistriAIError::MathOverflow.into());
+
+        // Periods recorded before weighting keep their equal split
+        let legacy = Reward {
+            unit_periodic_reward: 1_234,
+            weighted: false,
+            ..reward
+        };
+        assert_eq!(legacy.periodic_reward(0).unwrap(), 1_234);
+    }
 }
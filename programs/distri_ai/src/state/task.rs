@@ -1,4 +1,5 @@
 use anchor_lang::prelude::*;
+use crate::state::Versioned;
 
 #[account]
 #[derive(InitSpace)]
@@ -14,8 +15,18 @@ pub struct Task {
     /// The metadata by json format of this task.
     #[max_len(2048)]
     pub metadata: String,
+    /// The layout version of this account.
+    pub version: u8,
 }
 
 impl Task {
     pub const METADATA_MAX_LENGTH: usize = 2048;
 }
+
+impl Versioned for Task {
+    const VERSION: u8 = 1;
+
+    fn version(&self) -> u8 {
+        self.version
+    }
+}
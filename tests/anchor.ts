@@ -1,6 +1,7 @@
 import BN from "bn.js";
 import * as web3 from "@solana/web3.js";
 import * as anchor from "@coral-xyz/anchor";
+import { assert } from "chai";
 import type { Errors } from "../target/types/errors";
 
 describe("Test", () => {
@@ -34,14 +35,56 @@ describe("Test", () => {
     // Wait for transaction to confirm on the blockchain
     await program.provider.connection.confirmTransaction(txHash);
 
+    const [configPDA] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("config")],
+      program.programId
+    );
+    const config = await program.account.config.fetch(configPDA);
+
+    // updateConfig, so the stake can be withdrawn right after unstaking
+    txHash = await program.methods
+      .updateConfig(config.admin, { ...config, unbondingPeriod: new BN(0) })
+      .accounts({
+        config: configPDA,
+      })
+      .rpc();
+    console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+    await program.provider.connection.confirmTransaction(txHash);
+
+    // stakeMachine
+    txHash = await program.methods
+      .stakeMachine(config.minStake)
+      .accounts({
+        config: configPDA,
+        machine: machinePDA,
+        mint: config.mint,
+      })
+      .rpc();
+    console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+    await program.provider.connection.confirmTransaction(txHash);
+
     // makeOffer
+    const mint = new web3.PublicKey(
+      "896KfVVY6VRGQs1d9CKLnKUEgXXCCJcEEg7LwSK84vWE"
+    );
+    const [paymentMintPDA] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("payment-mint"), mint.toBuffer()],
+      program.programId
+    );
     const price = new BN(10_000_000_000);
     const maxDuration = 100;
     const disk = 1000;
+    // Bill orders by the minute
+    const billingGranularity = 60;
+    // Rent out a single GPU
+    const capacity = 1;
     txHash = await program.methods
-      .makeOffer(price, maxDuration, disk)
+      .makeOffer(price, maxDuration, disk, billingGranularity, capacity)
       .accounts({
+        config: configPDA,
         machine: machinePDA,
+        paymentMint: paymentMintPDA,
+        mint,
       })
       .rpc();
     console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
@@ -57,6 +100,29 @@ describe("Test", () => {
     console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
     await program.provider.connection.confirmTransaction(txHash);
 
+    // unstakeMachine
+    txHash = await program.methods
+      .unstakeMachine(config.minStake)
+      .accounts({
+        config: configPDA,
+        machine: machinePDA,
+      })
+      .rpc();
+    console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+    await program.provider.connection.confirmTransaction(txHash);
+
+    // withdrawStake
+    txHash = await program.methods
+      .withdrawStake()
+      .accounts({
+        config: configPDA,
+        machine: machinePDA,
+        mint: config.mint,
+      })
+      .rpc();
+    console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
+    await program.provider.connection.confirmTransaction(txHash);
+
     // removeMachine
     txHash = await program.methods
       .removeMachine()
@@ -67,4 +133,119 @@ describe("Test", () => {
     console.log(`Use 'solana confirm -v ${txHash}' to see the logs`);
     await program.provider.connection.confirmTransaction(txHash);
   });
+
+  it("renews at the order price", async () => {
+    const owner = program.provider.publicKey;
+    const uuid = Array.from(web3.Keypair.generate().publicKey.toBytes().slice(0, 16));
+    const [machinePDA] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("machine"), owner.toBuffer(), Uint8Array.from(uuid)],
+      program.programId
+    );
+    const [configPDA] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("config")],
+      program.programId
+    );
+    const config = await program.account.config.fetch(configPDA);
+    const mint = new web3.PublicKey(
+      "896KfVVY6VRGQs1d9CKLnKUEgXXCCJcEEg7LwSK84vWE"
+    );
+    const [paymentMintPDA] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("payment-mint"), mint.toBuffer()],
+      program.programId
+    );
+    const [calendarPDA] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("calendar"), machinePDA.toBuffer()],
+      program.programId
+    );
+
+    // Offer a staked, online machine with two units, so it stays for rent with one order
+    await program.methods
+      .addMachine(uuid, "{}")
+      .accounts({ machine: machinePDA })
+      .rpc();
+    await program.methods
+      .stakeMachine(config.minStake)
+      .accounts({
+        config: configPDA,
+        machine: machinePDA,
+        ownerAta: anchor.utils.token.associatedAddress({
+          mint: config.mint,
+          owner,
+        }),
+        stakeVault: anchor.web3.PublicKey.findProgramAddressSync(
+          [Buffer.from("stake"), machinePDA.toBuffer()],
+          program.programId
+        )[0],
+        mint: config.mint,
+      })
+      .rpc();
+    const price = new BN(1_000_000);
+    await program.methods
+      .makeOffer(price, 100, 1000, 60, 2)
+      .accounts({
+        config: configPDA,
+        machine: machinePDA,
+        paymentMint: paymentMintPDA,
+        mint,
+      })
+      .rpc();
+    await program.methods.heartbeat().accounts({ machine: machinePDA }).rpc();
+
+    // placeOrder and startOrder
+    const orderId = Array.from(web3.Keypair.generate().publicKey.toBytes().slice(0, 16));
+    const [orderPDA] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("order"), owner.toBuffer(), Uint8Array.from(orderId)],
+      program.programId
+    );
+    const [escrowPDA] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow"), orderPDA.toBuffer()],
+      program.programId
+    );
+    const buyerAta = anchor.utils.token.associatedAddress({ mint, owner });
+    await program.methods
+      .placeOrder(orderId, 1, 1, "{}", price, owner)
+      .accounts({
+        config: configPDA,
+        machine: machinePDA,
+        calendar: calendarPDA,
+        order: orderPDA,
+        buyerAta,
+        escrow: escrowPDA,
+        paymentMint: paymentMintPDA,
+        mint,
+      })
+      .rpc();
+    await program.methods
+      .startOrder()
+      .accounts({ order: orderPDA })
+      .rpc();
+
+    // The seller doubles the price before the buyer renews
+    await program.methods
+      .updateOffer(price.muln(2), 100, 1000, null)
+      .accounts({ machine: machinePDA })
+      .rpc();
+    await program.methods
+      .renewOrder(2, price, owner)
+      .accounts({
+        config: configPDA,
+        machine: machinePDA,
+        calendar: calendarPDA,
+        order: orderPDA,
+        buyerAta,
+        escrow: escrowPDA,
+        mint,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    // The order total is what the escrow actually holds, billed at the order price
+    const order = await program.account.order.fetch(orderPDA, "confirmed");
+    const escrow = await program.provider.connection.getTokenAccountBalance(
+      escrowPDA,
+      "confirmed"
+    );
+    assert.equal(order.duration, 3);
+    assert.equal(escrow.value.amount, order.total.toString());
+    assert.isTrue(order.total.lte(price.muln(3)));
+  });
 });